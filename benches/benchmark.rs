@@ -1,4 +1,4 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use geojson_vt_rs::{geojson_to_tile, GeoJSONVT, Options, TileOptions};
 use std::fs;
 use std::hint::black_box;
@@ -25,7 +25,7 @@ fn generate_tile_index(c: &mut Criterion) {
 
     c.bench_function("GenerateTileIndex", |b| {
         b.iter(|| {
-            let index = GeoJSONVT::from_geojson(&features, &options);
+            let index = GeoJSONVT::from_geojson(&features, &options).unwrap();
             black_box(index);
         })
     });
@@ -43,7 +43,7 @@ fn traverse_tile_pyramid(c: &mut Criterion) {
         ..Options::default()
     };
 
-    let mut index = GeoJSONVT::from_geojson(&features, &options);
+    let index = GeoJSONVT::from_geojson(&features, &options).unwrap();
 
     c.bench_function("TraverseTilePyramid", |b| {
         b.iter(|| {
@@ -52,7 +52,7 @@ fn traverse_tile_pyramid(c: &mut Criterion) {
                 let num_tiles = 2u32.pow(z as u32);
                 for x in 0..num_tiles {
                     for y in 0..num_tiles {
-                        index.get_tile(z, x, y);
+                        index.get_tile(z, x, y).unwrap();
                     }
                 }
             }
@@ -60,6 +60,37 @@ fn traverse_tile_pyramid(c: &mut Criterion) {
     });
 }
 
+#[cfg(feature = "mbtiles")]
+fn export_mbtiles(c: &mut Criterion) {
+    use geojson_vt_rs::mbtiles::{export_mbtiles, MbtilesOptions};
+
+    let json = fs::read_to_string("data/countries.geojson").unwrap();
+    let features = geojson::GeoJson::from_str(&json).unwrap();
+    let options = Options {
+        index_max_zoom: 7,
+        index_max_points: 200,
+        ..Options::default()
+    };
+    let mbtiles = MbtilesOptions {
+        max_zoom: 10,
+        compress: false,
+        ..MbtilesOptions::default()
+    };
+    let path = std::env::temp_dir().join("geojson-vt-benchmark.mbtiles");
+
+    let index = GeoJSONVT::from_geojson(&features, &options).unwrap();
+
+    c.bench_function("ExportMbtiles", |b| {
+        b.iter(|| {
+            let _ = fs::remove_file(&path);
+            export_mbtiles(&index, &path, &mbtiles).unwrap();
+        })
+    });
+}
+
+#[cfg(not(feature = "mbtiles"))]
+fn export_mbtiles(_: &mut Criterion) {}
+
 fn large_geo_json_parse(c: &mut Criterion) {
     let json = fs::read_to_string("fixtures/points.geojson").unwrap();
     c.bench_function("LargeGeoJSONParse", |b| {
@@ -76,21 +107,39 @@ fn large_geo_json_tile_index(c: &mut Criterion) {
 
     c.bench_function("LargeGeoJSONTileIndex", |b| {
         b.iter(|| {
-            let index = GeoJSONVT::from_geojson(&features, &options);
+            let index = GeoJSONVT::from_geojson(&features, &options).unwrap();
             black_box(index)
         })
     });
 }
 
+// the input is cloned outside of the measurement, as `from_owned` consumes it
+fn large_geo_json_tile_index_owned(c: &mut Criterion) {
+    let json = fs::read_to_string("fixtures/points.geojson").unwrap();
+    let features = geojson::GeoJson::from_str(&json).unwrap();
+    let options = Options::default();
+
+    c.bench_function("LargeGeoJSONTileIndexOwned", |b| {
+        b.iter_batched(
+            || features.clone(),
+            |features| {
+                let index = GeoJSONVT::from_owned(features, &options).unwrap();
+                black_box(index)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
 fn large_geo_json_get_tile(c: &mut Criterion) {
     let json = fs::read_to_string("fixtures/points.geojson").unwrap();
     let features = geojson::GeoJson::from_str(&json).unwrap();
     let options = Options::default();
 
-    let mut index = GeoJSONVT::from_geojson(&features, &options);
+    let index = GeoJSONVT::from_geojson(&features, &options).unwrap();
     c.bench_function("LargeGeoJSONGetTile", |b| {
         b.iter(|| {
-            index.get_tile(12, 1171, 1566);
+            index.get_tile(12, 1171, 1566).unwrap();
         })
     });
 }
@@ -107,7 +156,8 @@ fn large_geo_json_to_tile(c: &mut Criterion) {
                 &TileOptions::default(),
                 false,
                 true,
-            );
+            )
+            .unwrap();
         })
     });
 }
@@ -124,11 +174,11 @@ fn single_tile_index(c: &mut Criterion) {
         ..Options::default()
     };
 
-    let mut index = GeoJSONVT::from_geojson(&features, &options);
+    let index = GeoJSONVT::from_geojson(&features, &options).unwrap();
 
     c.bench_function("SingleTileIndex", |b| {
         b.iter(|| {
-            index.get_tile(12, 1171, 1566);
+            index.get_tile(12, 1171, 1566).unwrap();
         })
     });
 }
@@ -145,7 +195,8 @@ fn single_tile_geo_json_to_tile(c: &mut Criterion) {
                 &TileOptions::default(),
                 false,
                 true,
-            );
+            )
+            .unwrap();
         })
     });
 }
@@ -155,8 +206,10 @@ criterion_group!(
     parse_geo_json,
     generate_tile_index,
     traverse_tile_pyramid,
+    export_mbtiles,
     large_geo_json_parse,
     large_geo_json_tile_index,
+    large_geo_json_tile_index_owned,
     large_geo_json_get_tile,
     large_geo_json_to_tile,
     single_tile_index,
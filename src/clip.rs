@@ -384,8 +384,6 @@ pub fn clip<const I: usize>(
 
     for feature in features {
         let geom = &feature.geometry;
-        let props = &feature.properties;
-        let id = &feature.id;
 
         let min = GetCoordinate::<I>::get(&feature.bbox.min);
         let max = GetCoordinate::<I>::get(&feature.bbox.max);
@@ -404,24 +402,19 @@ pub fn clip<const I: usize>(
                     if line_metrics {
                         for segment in result {
                             clipped.push(
-                                VtFeature::new(
-                                    VtGeometry::LineString(segment.clone()),
-                                    props.clone(),
-                                    id.clone(),
-                                )
-                                .unwrap(),
+                                feature
+                                    .with_geometry(VtGeometry::LineString(segment.clone()))
+                                    .unwrap(),
                             );
                         }
                     } else {
-                        if let Some(feature) =
-                            VtFeature::new(clipped_geom, props.clone(), id.clone())
-                        {
+                        if let Some(feature) = feature.with_geometry(clipped_geom) {
                             clipped.push(feature);
                         }
                     }
                 }
                 _ => {
-                    if let Some(feature) = VtFeature::new(clipped_geom, props.clone(), id.clone()) {
+                    if let Some(feature) = feature.with_geometry(clipped_geom) {
                         clipped.push(feature);
                     }
                 }
@@ -0,0 +1,523 @@
+use std::sync::Arc;
+
+use geojson::feature::Id;
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue, PointType, Value};
+use serde_json::Number;
+
+use crate::compact::{CompactFeature, CompactGeometry, CompactTile};
+use crate::convert::validate_geometry;
+use crate::error::{check_tile, Error, MAX_ZOOM};
+use crate::kdtree::KdTree;
+use crate::projection::{Projection, WebMercator};
+use crate::tile::{json_number, Tile};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+    Mean, // weighted by the number of points with a value
+}
+
+// A property of the clusters, aggregated from a numeric property of their points
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterProperty {
+    pub name: String,     // name of the cluster property
+    pub property: String, // property of the points, non-numeric values are ignored
+    pub aggregate: Aggregate,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterOptions {
+    pub min_zoom: u8,                     // min zoom to generate clusters on
+    pub max_zoom: u8,                     // max zoom to cluster points on; can't be higher than 24
+    pub min_points: u32,                  // minimum number of points to form a cluster
+    pub radius: f64,                      // cluster radius in pixels
+    pub extent: u16,                      // tile extent, the radius is relative to it
+    pub node_size: usize,                 // size of the KD-tree leaf nodes
+    pub generate_id: bool,                // whether to generate point ids, overriding existing ids
+    pub properties: Vec<ClusterProperty>, // properties aggregated over the points of a cluster
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            min_zoom: 0,
+            max_zoom: 16,
+            min_points: 2,
+            radius: 40.,
+            extent: 512,
+            node_size: 64,
+            generate_id: false,
+            properties: Vec::new(),
+        }
+    }
+}
+
+// Aggregated value and the number of points it was taken from
+#[derive(Clone, Copy, Default)]
+struct Accumulator {
+    value: f64,
+    count: u32,
+}
+
+impl Accumulator {
+    fn add(&mut self, other: &Accumulator, aggregate: Aggregate) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        self.value = match aggregate {
+            Aggregate::Sum => self.value + other.value,
+            Aggregate::Min => self.value.min(other.value),
+            Aggregate::Max => self.value.max(other.value),
+            Aggregate::Mean => {
+                (self.value * self.count as f64 + other.value * other.count as f64)
+                    / (self.count + other.count) as f64
+            }
+        };
+        self.count += other.count;
+    }
+}
+
+// A point or cluster in the tree of a zoom level
+#[derive(Clone)]
+struct Node {
+    x: f64,
+    y: f64,
+    zoom: u8,            // zoom the node was visited at, u8::MAX before
+    id: u64,             // position of the point, or the cluster id
+    parent: Option<u64>, // cluster the node was merged into at the zoom below
+    num_points: u32,
+    values: Vec<Accumulator>, // one per cluster property
+}
+
+struct Level {
+    tree: KdTree,
+    nodes: Vec<Node>,
+}
+
+impl Level {
+    fn new(nodes: Vec<Node>, node_size: usize) -> Self {
+        Self {
+            tree: KdTree::new(nodes.iter().map(|node| [node.x, node.y]), node_size),
+            nodes,
+        }
+    }
+}
+
+struct ClusterPoint {
+    position: PointType,
+    properties: Arc<JsonObject>,
+    id: Option<Arc<Id>>,
+}
+
+// Clusters points which are closer than the radius at a zoom level, ported from supercluster
+pub struct Supercluster {
+    options: ClusterOptions,
+    points: Vec<ClusterPoint>,
+    levels: Vec<Level>, // indexed by zoom, up to max_zoom + 1 which holds the single points
+}
+
+impl Supercluster {
+    // Features without a valid Point geometry are skipped.
+    pub fn new(features: &FeatureCollection, options: &ClusterOptions) -> Result<Self, Error> {
+        if options.max_zoom > MAX_ZOOM {
+            return Err(Error::ZoomOutOfRange {
+                z: options.max_zoom,
+                max_zoom: MAX_ZOOM,
+            });
+        }
+
+        let mut points = Vec::new();
+        for (i, feature) in features.features.iter().enumerate() {
+            let Some(geometry) = &feature.geometry else {
+                continue;
+            };
+            let Value::Point(position) = &geometry.value else {
+                continue;
+            };
+            if validate_geometry(geometry, &WebMercator).is_err() {
+                continue;
+            }
+            // generated ids are the position in the input, like in `GeoJSONVT`
+            let id = if options.generate_id {
+                Some(Id::Number(Number::from(i)))
+            } else {
+                feature.id.clone()
+            };
+            points.push(ClusterPoint {
+                position: position.clone(),
+                properties: Arc::new(feature.properties.clone().unwrap_or_default()),
+                id: id.map(Arc::new),
+            });
+        }
+
+        let mut index = Self {
+            options: options.clone(),
+            points,
+            levels: Vec::new(),
+        };
+
+        let nodes = index
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                let [x, y] = WebMercator.project(&point.position);
+                Node {
+                    x,
+                    y,
+                    zoom: u8::MAX,
+                    id: i as u64,
+                    parent: None,
+                    num_points: 1,
+                    values: index.point_values(&point.properties),
+                }
+            })
+            .collect();
+
+        let max_zoom = options.max_zoom as usize;
+        let mut levels: Vec<Level> = (0..=max_zoom)
+            .map(|_| Level::new(Vec::new(), options.node_size))
+            .collect();
+        levels.push(Level::new(nodes, options.node_size));
+        for z in (options.min_zoom as usize..=max_zoom).rev() {
+            let nodes = index.cluster(&mut levels[z + 1], z as u8);
+            levels[z] = Level::new(nodes, options.node_size);
+        }
+        index.levels = levels;
+
+        Ok(index)
+    }
+
+    fn point_values(&self, properties: &JsonObject) -> Vec<Accumulator> {
+        self.options
+            .properties
+            .iter()
+            .map(|property| {
+                let value = properties
+                    .get(&property.property)
+                    .and_then(JsonValue::as_f64);
+                value.map_or_else(Accumulator::default, |value| Accumulator {
+                    value,
+                    count: 1,
+                })
+            })
+            .collect()
+    }
+
+    // Merges the nodes of the zoom above within the radius of each other into the nodes of `zoom`
+    fn cluster(&self, level: &mut Level, zoom: u8) -> Vec<Node> {
+        let r = self.options.radius / (self.options.extent as f64 * 2f64.powi(zoom as i32));
+        let mut next = Vec::new();
+
+        for i in 0..level.nodes.len() {
+            if level.nodes[i].zoom <= zoom {
+                continue;
+            }
+            level.nodes[i].zoom = zoom;
+
+            let (x, y, origin_points) = {
+                let node = &level.nodes[i];
+                (node.x, node.y, node.num_points)
+            };
+            let neighbors = level.tree.within(x, y, r);
+            let num_points = origin_points
+                + neighbors
+                    .iter()
+                    .map(|&k| &level.nodes[k])
+                    .filter(|neighbor| neighbor.zoom > zoom)
+                    .map(|neighbor| neighbor.num_points)
+                    .sum::<u32>();
+
+            if num_points > origin_points && num_points >= self.options.min_points {
+                // encodes the position of the origin and the zoom it comes from
+                let id = ((i as u64) << 5) + zoom as u64 + 1 + self.points.len() as u64;
+                let mut wx = x * origin_points as f64;
+                let mut wy = y * origin_points as f64;
+                let mut values = level.nodes[i].values.clone();
+
+                for k in neighbors {
+                    let neighbor = &mut level.nodes[k];
+                    if neighbor.zoom <= zoom {
+                        continue;
+                    }
+                    neighbor.zoom = zoom;
+                    neighbor.parent = Some(id);
+                    wx += neighbor.x * neighbor.num_points as f64;
+                    wy += neighbor.y * neighbor.num_points as f64;
+                    for ((value, other), property) in values
+                        .iter_mut()
+                        .zip(&neighbor.values)
+                        .zip(&self.options.properties)
+                    {
+                        value.add(other, property.aggregate);
+                    }
+                }
+                level.nodes[i].parent = Some(id);
+
+                next.push(Node {
+                    x: wx / num_points as f64,
+                    y: wy / num_points as f64,
+                    zoom: u8::MAX,
+                    id,
+                    parent: None,
+                    num_points,
+                    values,
+                });
+            } else {
+                next.push(carried(&level.nodes[i]));
+                // the neighbors didn't form a cluster either
+                if num_points > 1 {
+                    for k in neighbors {
+                        let neighbor = &mut level.nodes[k];
+                        if neighbor.zoom <= zoom {
+                            continue;
+                        }
+                        neighbor.zoom = zoom;
+                        next.push(carried(neighbor));
+                    }
+                }
+            }
+        }
+        next
+    }
+
+    fn limit_zoom(&self, z: u8) -> usize {
+        z.max(self.options.min_zoom).min(self.options.max_zoom + 1) as usize
+    }
+
+    // Points and clusters of the tile, with the extent of the options. Points within the radius
+    // of the tile are included, so clusters at the edges are in both tiles.
+    pub fn get_tile_compact(&self, z: u8, x: u32, y: u32) -> Result<CompactTile, Error> {
+        check_tile(z, y, MAX_ZOOM)?;
+
+        let level = &self.levels[self.limit_zoom(z)];
+        let z2 = 1u32 << z;
+        let x = x % z2; // wrap tile x coordinate
+        let z2f = z2 as f64;
+        let p = self.options.radius / self.options.extent as f64;
+        let top = (y as f64 - p) / z2f;
+        let bottom = (y as f64 + 1. + p) / z2f;
+
+        let mut tile = CompactTile::default();
+        let ids = level
+            .tree
+            .range((x as f64 - p) / z2f, top, (x as f64 + 1. + p) / z2f, bottom);
+        self.add_tile_features(&mut tile, level, ids, x as f64, y as f64, z2f);
+        // points across the antimeridian
+        if x == 0 {
+            let ids = level.tree.range(1. - p / z2f, top, 1., bottom);
+            self.add_tile_features(&mut tile, level, ids, z2f, y as f64, z2f);
+        }
+        if x == z2 - 1 {
+            let ids = level.tree.range(0., top, p / z2f, bottom);
+            self.add_tile_features(&mut tile, level, ids, -1., y as f64, z2f);
+        }
+        Ok(tile)
+    }
+
+    pub fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Tile, Error> {
+        Ok(self.get_tile_compact(z, x, y)?.to_geojson())
+    }
+
+    fn add_tile_features(
+        &self,
+        tile: &mut CompactTile,
+        level: &Level,
+        ids: Vec<usize>,
+        x: f64,
+        y: f64,
+        z2: f64,
+    ) {
+        let extent = self.options.extent as f64;
+        for i in ids {
+            let node = &level.nodes[i];
+            let point = [
+                (extent * (node.x * z2 - x)).round() as i32,
+                (extent * (node.y * z2 - y)).round() as i32,
+            ];
+            let (properties, id) = if node.num_points > 1 {
+                (
+                    Arc::new(self.cluster_properties(node)),
+                    Some(Arc::new(Id::Number(Number::from(node.id)))),
+                )
+            } else {
+                let point = &self.points[node.id as usize];
+                (point.properties.clone(), point.id.clone())
+            };
+            tile.num_points += 1;
+            tile.num_simplified += 1;
+            tile.features.push(CompactFeature {
+                geometry: CompactGeometry::Points(vec![point]),
+                properties,
+                id,
+            });
+        }
+    }
+
+    fn cluster_properties(&self, node: &Node) -> JsonObject {
+        let mut properties = JsonObject::new();
+        properties.insert("cluster".to_string(), JsonValue::Bool(true));
+        properties.insert("cluster_id".to_string(), JsonValue::from(node.id));
+        properties.insert("point_count".to_string(), JsonValue::from(node.num_points));
+        properties.insert(
+            "point_count_abbreviated".to_string(),
+            abbreviated(node.num_points),
+        );
+        for (value, property) in node.values.iter().zip(&self.options.properties) {
+            if value.count > 0 {
+                properties.insert(property.name.clone(), json_number(value.value));
+            }
+        }
+        properties
+    }
+
+    // Nodes merged into the cluster, around the node it was formed at in the level above
+    fn children(&self, cluster_id: u64) -> Result<Vec<&Node>, Error> {
+        let not_found = Error::ClusterNotFound { cluster_id };
+        let Some(offset) = cluster_id.checked_sub(self.points.len() as u64) else {
+            return Err(not_found);
+        };
+        let (origin, zoom) = ((offset >> 5) as usize, (offset % 32) as usize);
+        let Some(node) = self
+            .levels
+            .get(zoom)
+            .and_then(|level| level.nodes.get(origin))
+        else {
+            return Err(not_found);
+        };
+
+        let level = &self.levels[zoom];
+        let r = self.options.radius / (self.options.extent as f64 * 2f64.powi(zoom as i32 - 1));
+        let children: Vec<&Node> = level
+            .tree
+            .within(node.x, node.y, r)
+            .into_iter()
+            .map(|k| &level.nodes[k])
+            .filter(|child| child.parent == Some(cluster_id))
+            .collect();
+        if children.is_empty() {
+            return Err(not_found);
+        }
+        Ok(children)
+    }
+
+    // Points and clusters a cluster was merged from at the zoom above, in longitude and latitude
+    pub fn get_cluster_children(&self, cluster_id: u64) -> Result<Vec<Feature>, Error> {
+        Ok(self
+            .children(cluster_id)?
+            .into_iter()
+            .map(|child| self.node_feature(child))
+            .collect())
+    }
+
+    // Points of a cluster, skipping `offset` points and returning at most `limit`
+    pub fn get_cluster_leaves(
+        &self,
+        cluster_id: u64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Feature>, Error> {
+        let mut leaves = Vec::new();
+        if limit > 0 {
+            self.append_leaves(&mut leaves, cluster_id, limit, offset, 0)?;
+        }
+        Ok(leaves)
+    }
+
+    fn append_leaves(
+        &self,
+        leaves: &mut Vec<Feature>,
+        cluster_id: u64,
+        limit: usize,
+        offset: usize,
+        mut skipped: usize,
+    ) -> Result<usize, Error> {
+        for child in self.children(cluster_id)? {
+            if child.num_points > 1 {
+                if skipped + child.num_points as usize <= offset {
+                    skipped += child.num_points as usize;
+                } else {
+                    skipped = self.append_leaves(leaves, child.id, limit, offset, skipped)?;
+                }
+            } else if skipped < offset {
+                skipped += 1;
+            } else {
+                leaves.push(self.node_feature(child));
+            }
+            if leaves.len() == limit {
+                break;
+            }
+        }
+        Ok(skipped)
+    }
+
+    // Zoom at which the cluster splits into several children
+    pub fn get_cluster_expansion_zoom(&self, cluster_id: u64) -> Result<u8, Error> {
+        // the children are looked up first so that an id of no cluster fails at any zoom
+        let mut children = self.children(cluster_id)?;
+        let offset = cluster_id - self.points.len() as u64;
+        let Some(mut zoom) = ((offset % 32) as u8).checked_sub(1) else {
+            return Err(Error::ClusterNotFound { cluster_id });
+        };
+        while zoom <= self.options.max_zoom {
+            zoom += 1;
+            match children.as_slice() {
+                [child] if child.num_points > 1 => children = self.children(child.id)?,
+                _ => break,
+            }
+        }
+        Ok(zoom)
+    }
+
+    fn node_feature(&self, node: &Node) -> Feature {
+        if node.num_points > 1 {
+            return Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(Value::Point(
+                    WebMercator.unproject(node.x, node.y),
+                ))),
+                id: Some(Id::Number(Number::from(node.id))),
+                properties: Some(self.cluster_properties(node)),
+                foreign_members: None,
+            };
+        }
+
+        let point = &self.points[node.id as usize];
+        Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(Value::Point(point.position.clone()))),
+            id: point.id.as_deref().cloned(),
+            properties: if point.properties.is_empty() {
+                None
+            } else {
+                Some(JsonObject::clone(&point.properties))
+            },
+            foreign_members: None,
+        }
+    }
+}
+
+// Copy of a node that wasn't merged, for the zoom below
+fn carried(node: &Node) -> Node {
+    Node {
+        zoom: u8::MAX,
+        parent: None,
+        ..node.clone()
+    }
+}
+
+fn abbreviated(count: u32) -> JsonValue {
+    if count >= 10000 {
+        JsonValue::String(format!("{}k", (count as f64 / 1000.).round()))
+    } else if count >= 1000 {
+        JsonValue::String(format!("{}k", (count as f64 / 100.).round() / 10.))
+    } else {
+        JsonValue::from(count)
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use geojson::feature::Id;
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+
+use crate::tile::Tile;
+
+// Tile coordinates, between minus the buffer and the extent plus the buffer
+pub type TilePoint = [i32; 2];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompactGeometry {
+    Points(Vec<TilePoint>),
+    Lines(Vec<Vec<TilePoint>>),
+    Polygons(Vec<Vec<Vec<TilePoint>>>), // rings of every polygon, the outer ring first
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactFeature {
+    pub geometry: CompactGeometry,
+    pub properties: Arc<JsonObject>, // shared with the source feature
+    pub id: Option<Arc<Id>>,
+}
+
+// Tile with integer coordinates, the GeoJSON `Tile` is a view over it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactTile {
+    pub features: Vec<CompactFeature>,
+    pub num_points: u32,
+    pub num_simplified: u32,
+}
+
+impl CompactTile {
+    // Geometries with a single part become a Point, LineString or Polygon
+    pub fn to_geojson(&self) -> Tile {
+        let features = self
+            .features
+            .iter()
+            .map(|feature| Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(feature.geometry.to_geojson())),
+                id: feature.id.as_deref().cloned(),
+                properties: if feature.properties.is_empty() {
+                    None
+                } else {
+                    Some(JsonObject::clone(&feature.properties))
+                },
+                foreign_members: None,
+            })
+            .collect();
+
+        Tile {
+            features: FeatureCollection {
+                bbox: None,
+                features,
+                foreign_members: None,
+            },
+            num_points: self.num_points,
+            num_simplified: self.num_simplified,
+        }
+    }
+}
+
+impl CompactGeometry {
+    fn to_geojson(&self) -> Value {
+        match self {
+            CompactGeometry::Points(points) => match points.as_slice() {
+                [point] => Value::Point(position(point)),
+                points => Value::MultiPoint(positions(points)),
+            },
+            CompactGeometry::Lines(lines) => match lines.as_slice() {
+                [line] => Value::LineString(positions(line)),
+                lines => Value::MultiLineString(lines.iter().map(|line| positions(line)).collect()),
+            },
+            CompactGeometry::Polygons(polygons) => match polygons.as_slice() {
+                [rings] => Value::Polygon(rings.iter().map(|ring| positions(ring)).collect()),
+                polygons => Value::MultiPolygon(
+                    polygons
+                        .iter()
+                        .map(|rings| rings.iter().map(|ring| positions(ring)).collect())
+                        .collect(),
+                ),
+            },
+        }
+    }
+}
+
+fn position(point: &TilePoint) -> Vec<f64> {
+    vec![point[0] as f64, point[1] as f64]
+}
+
+fn positions(points: &[TilePoint]) -> Vec<Vec<f64>> {
+    points.iter().map(position).collect()
+}
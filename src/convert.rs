@@ -1,34 +1,37 @@
-use std::f64::consts::PI;
+use std::sync::Arc;
 
 use geojson::feature::Id;
-use geojson::{FeatureCollection, Geometry, LineStringType, PointType, PolygonType, Value};
+use geojson::{Feature, Geometry, JsonObject, JsonValue, PointType, PolygonType, Value};
 use serde_json::Number;
 
+use crate::error::{Error, GeometryError, InvalidFeature};
+use crate::projection::Projection;
 use crate::simplify::simplify_wrapper;
 use crate::types::{
     VtEmpty, VtFeature, VtFeatures, VtGeometry, VtGeometryCollection, VtLineString, VtLinearRing,
     VtMultiLineString, VtMultiPoint, VtMultiPolygon, VtPoint, VtPolygon,
 };
-use crate::{LinearRingType, MultiLineStringType, MultiPointType, MultiPolygonType};
+use crate::{
+    FeatureZoom, MultiLineStringType, MultiPointType, MultiPolygonType, Options, PromoteId,
+    ValidationPolicy,
+};
 
-pub struct Project {
+pub struct Project<'a> {
     pub tolerance: f64,
+    pub projection: &'a dyn Projection,
 }
 
-impl Project {
+impl Project<'_> {
     // TODO
+    #[allow(dead_code)]
     pub fn project_empty(&self) -> VtEmpty {}
 
-    pub fn project_point(&self, p: PointType) -> VtPoint {
-        let sine = (p[1] * PI / 180.).sin();
-        let x = p[0] / 360. + 0.5;
-        let y = (0.5 - 0.25 * ((1. + sine) / (1. - sine)).ln() / PI)
-            .min(1.0)
-            .max(0.0);
+    pub fn project_point(&self, p: &[f64]) -> VtPoint {
+        let [x, y] = self.projection.project(p);
         VtPoint { x, y, z: 0.0 }
     }
 
-    pub fn project_line_string(&self, points: LineStringType) -> VtLineString {
+    pub fn project_line_string(&self, points: &[PointType]) -> VtLineString {
         let mut result = VtLineString::default();
         let len = points.len();
 
@@ -56,7 +59,7 @@ impl Project {
         result
     }
 
-    pub fn project_linear_ring(&self, ring: LinearRingType) -> VtLinearRing {
+    pub fn project_linear_ring(&self, ring: &[PointType]) -> VtLinearRing {
         let mut result: VtLinearRing = VtLinearRing::default();
         let len = ring.len();
 
@@ -86,11 +89,9 @@ impl Project {
 
     pub fn project_geometry(&self, geometry: &Geometry) -> VtGeometry {
         match &geometry.value {
-            Value::Point(value) => VtGeometry::Point(self.project_point(value.clone())),
+            Value::Point(value) => VtGeometry::Point(self.project_point(value)),
             Value::MultiPoint(value) => VtGeometry::MultiPoint(self.project_multi_point(value)),
-            Value::LineString(value) => {
-                VtGeometry::LineString(self.project_line_string(value.clone()))
-            }
+            Value::LineString(value) => VtGeometry::LineString(self.project_line_string(value)),
             Value::MultiLineString(value) => {
                 VtGeometry::MultiLineString(self.project_multi_line_string(value))
             }
@@ -107,7 +108,7 @@ impl Project {
     pub fn project_multi_point(&self, vector: &MultiPointType) -> VtMultiPoint {
         let mut result = Vec::with_capacity(vector.len());
         for e in vector {
-            result.push(self.project_point(e.clone()));
+            result.push(self.project_point(e));
         }
         result
     }
@@ -115,7 +116,7 @@ impl Project {
     pub fn project_multi_line_string(&self, vector: &MultiLineStringType) -> VtMultiLineString {
         let mut result = Vec::with_capacity(vector.len());
         for e in vector {
-            result.push(self.project_line_string(e.clone()));
+            result.push(self.project_line_string(e));
         }
         result
     }
@@ -131,7 +132,7 @@ impl Project {
     pub fn project_polygon(&self, vector: &PolygonType) -> VtPolygon {
         let mut result = Vec::with_capacity(vector.len());
         for e in vector {
-            result.push(self.project_linear_ring(e.clone()));
+            result.push(self.project_linear_ring(e));
         }
         result
     }
@@ -145,32 +146,271 @@ impl Project {
     }
 }
 
-pub fn convert(features: &FeatureCollection, tolerance: f64, generate_id: bool) -> VtFeatures {
-    let mut projected = Vec::with_capacity(features.features.len());
+fn validate_positions<'a, I>(positions: I, projection: &dyn Projection) -> Result<(), GeometryError>
+where
+    I: IntoIterator<Item = &'a PointType>,
+{
+    for position in positions {
+        if position.len() < 2 {
+            return Err(GeometryError::InvalidPosition(position.len()));
+        }
+        if !position[0].is_finite() || !position[1].is_finite() {
+            return Err(GeometryError::NonFiniteCoordinate);
+        }
+        projection.validate(position)?;
+    }
+    Ok(())
+}
 
-    let mut gen_id: u64 = 0;
-    for feature in features {
-        let mut feature_id = feature.id.clone();
-        if generate_id {
-            feature_id = Some(Id::Number(Number::from(gen_id)));
-            gen_id += 1;
+fn validate_polygon(
+    polygon: &PolygonType,
+    projection: &dyn Projection,
+) -> Result<(), GeometryError> {
+    for ring in polygon {
+        // a closed ring needs at least three distinct points
+        if ring.len() < 4 {
+            return Err(GeometryError::TooFewPoints(ring.len()));
         }
+        validate_positions(ring, projection)?;
+    }
+    Ok(())
+}
 
-        let project = Project { tolerance };
+// Checks that a geometry can be projected into a meaningful tile geometry
+pub fn validate_geometry(
+    geometry: &Geometry,
+    projection: &dyn Projection,
+) -> Result<(), GeometryError> {
+    match &geometry.value {
+        Value::Point(value) => validate_positions([value], projection),
+        Value::MultiPoint(value) | Value::LineString(value) => {
+            validate_positions(value, projection)
+        }
+        Value::MultiLineString(value) => validate_positions(value.iter().flatten(), projection),
+        Value::Polygon(value) => validate_polygon(value, projection),
+        Value::MultiPolygon(value) => value
+            .iter()
+            .try_for_each(|polygon| validate_polygon(polygon, projection)),
+        Value::GeometryCollection(value) => value
+            .iter()
+            .try_for_each(|geometry| validate_geometry(geometry, projection)),
+    }
+}
 
-        let feature = VtFeature::new(
-            project.project_geometry(feature.geometry.as_ref().unwrap()),
-            feature
-                .properties
-                .clone()
-                .unwrap_or_default()
-                .into_iter()
-                .collect(), // TODO is this unwrapping oke?
-            feature_id.clone(),
-        );
-        if let Some(feature) = feature {
+// Input of the conversion, implemented for bare geometries so they don't need to be copied into
+// a feature, and for references, which clone the properties instead of moving them
+pub trait SourceFeature {
+    fn geometry(&self) -> Option<&Geometry>;
+    fn properties(&self) -> Option<&JsonObject>;
+    fn id(&self) -> Option<&Id>;
+    fn foreign_members(&self) -> Option<&JsonObject>;
+    // properties of the converted feature, moved out of owned input
+    fn into_properties(self) -> JsonObject;
+}
+
+impl SourceFeature for Feature {
+    fn geometry(&self) -> Option<&Geometry> {
+        self.geometry.as_ref()
+    }
+
+    fn properties(&self) -> Option<&JsonObject> {
+        self.properties.as_ref()
+    }
+
+    fn id(&self) -> Option<&Id> {
+        self.id.as_ref()
+    }
+
+    fn foreign_members(&self) -> Option<&JsonObject> {
+        self.foreign_members.as_ref()
+    }
+
+    fn into_properties(self) -> JsonObject {
+        self.properties.unwrap_or_default()
+    }
+}
+
+impl SourceFeature for Geometry {
+    fn geometry(&self) -> Option<&Geometry> {
+        Some(self)
+    }
+
+    fn properties(&self) -> Option<&JsonObject> {
+        None
+    }
+
+    fn id(&self) -> Option<&Id> {
+        None
+    }
+
+    fn foreign_members(&self) -> Option<&JsonObject> {
+        None
+    }
+
+    fn into_properties(self) -> JsonObject {
+        JsonObject::new()
+    }
+}
+
+impl<T: SourceFeature + ?Sized> SourceFeature for &T {
+    fn geometry(&self) -> Option<&Geometry> {
+        (**self).geometry()
+    }
+
+    fn properties(&self) -> Option<&JsonObject> {
+        (**self).properties()
+    }
+
+    fn id(&self) -> Option<&Id> {
+        (**self).id()
+    }
+
+    fn foreign_members(&self) -> Option<&JsonObject> {
+        (**self).foreign_members()
+    }
+
+    fn into_properties(self) -> JsonObject {
+        self.properties().cloned().unwrap_or_default()
+    }
+}
+
+pub fn convert<F: SourceFeature>(
+    features: impl IntoIterator<Item = F>,
+    tolerance: f64,
+    options: &Options,
+) -> Result<(VtFeatures, Vec<InvalidFeature>), Error> {
+    let mut projected = Vec::new();
+    let mut invalid = Vec::new();
+
+    for (i, feature) in features.into_iter().enumerate() {
+        if let Some(feature) = convert_feature(feature, i, tolerance, options, &mut invalid)? {
             projected.push(feature);
         }
     }
-    projected
+    Ok((projected, invalid))
+}
+
+// Converts the feature at position `i` of the input. Invalid features are handled according to
+// the `validation` option, `None` is returned for dropped and empty features.
+pub fn convert_feature<F: SourceFeature>(
+    feature: F,
+    i: usize,
+    tolerance: f64,
+    options: &Options,
+    invalid: &mut Vec<InvalidFeature>,
+) -> Result<Option<VtFeature>, Error> {
+    // features left out by the filter aren't validated
+    if let Some(filter) = &options.filter {
+        if !filter.matches(feature.geometry(), feature.properties(), feature.id()) {
+            return Ok(None);
+        }
+    }
+
+    let geometry = match feature
+        .geometry()
+        .ok_or(GeometryError::MissingGeometry)
+        .and_then(|geometry| {
+            validate_geometry(geometry, options.projection.as_ref()).map(|_| geometry)
+        }) {
+        Ok(geometry) => geometry,
+        Err(error) => match options.validation {
+            ValidationPolicy::Skip => return Ok(None),
+            ValidationPolicy::Fail => return Err(Error::InvalidGeometry { feature: i, error }),
+            ValidationPolicy::Report => {
+                invalid.push(InvalidFeature {
+                    feature: i,
+                    id: feature.id().cloned(),
+                    error,
+                });
+                return Ok(None);
+            }
+        },
+    };
+
+    // generated ids are the position in the input, so skipping features doesn't shift them
+    let feature_id = match &options.promote_id {
+        Some(promote_id) => promoted_id(promote_id, feature.properties()),
+        None if options.generate_id => Some(Id::Number(Number::from(i))),
+        None => feature.id().cloned(),
+    };
+    let feature_id = feature_id.map(Arc::new);
+
+    let (min_zoom, max_zoom) = options
+        .feature_zoom
+        .as_ref()
+        .map_or((0, u8::MAX), |zoom| zoom_range(zoom, &feature));
+
+    let project = Project {
+        tolerance,
+        projection: options.projection.as_ref(),
+    };
+    let geometry = project.project_geometry(geometry);
+
+    // the properties left out of the tiles aren't kept at all, unless the predicate needs them
+    let mut properties = feature.into_properties();
+    if options.tile.feature_predicate.is_none() {
+        options.tile.properties.apply(&mut properties);
+    }
+
+    Ok(
+        VtFeature::new(geometry, Arc::new(properties), feature_id).map(|mut feature| {
+            feature.min_zoom = min_zoom;
+            feature.max_zoom = max_zoom;
+            feature
+        }),
+    )
+}
+
+// Id of the feature from its properties. Strings holding an integer become numbers, so the id is
+// the same whether the source stored it as a string or a number.
+fn promoted_id(promote_id: &PromoteId, properties: Option<&JsonObject>) -> Option<Id> {
+    let id = match promote_id {
+        PromoteId::Property(name) => match properties?.get(name)? {
+            JsonValue::Number(number) => Id::Number(number.clone()),
+            JsonValue::String(string) => Id::String(string.clone()),
+            _ => return None,
+        },
+        PromoteId::Callback(callback) => callback(properties.unwrap_or(&JsonObject::new()))?,
+    };
+    // only strings written the way the number is, so "007" and "+7" stay distinct from "7"
+    let number = |string: &str| {
+        let number = match string.parse::<u64>() {
+            Ok(number) => Number::from(number),
+            Err(_) => Number::from(string.parse::<i64>().ok()?),
+        };
+        (number.to_string() == string).then_some(number)
+    };
+    Some(match id {
+        Id::String(string) => match number(&string) {
+            Some(number) => Id::Number(number),
+            None => Id::String(string),
+        },
+        id => id,
+    })
+}
+
+fn zoom_range<F: SourceFeature>(feature_zoom: &FeatureZoom, feature: &F) -> (u8, u8) {
+    let zoom = |object: Option<&JsonObject>, key: &str| {
+        object
+            .and_then(|object| object.get(key))
+            .and_then(JsonValue::as_f64)
+            .map(|zoom| zoom.clamp(0., u8::MAX as f64) as u8)
+    };
+    let (min_zoom, max_zoom) = match feature_zoom {
+        FeatureZoom::Properties { min_zoom, max_zoom } => (
+            zoom(feature.properties(), min_zoom),
+            zoom(feature.properties(), max_zoom),
+        ),
+        FeatureZoom::Tippecanoe => {
+            let tippecanoe = feature
+                .foreign_members()
+                .and_then(|members| members.get("tippecanoe"))
+                .and_then(JsonValue::as_object);
+            (zoom(tippecanoe, "minzoom"), zoom(tippecanoe, "maxzoom"))
+        }
+        FeatureZoom::Callback(callback) => {
+            return callback(feature.properties().unwrap_or(&JsonObject::new()))
+        }
+    };
+    (min_zoom.unwrap_or(0), max_zoom.unwrap_or(u8::MAX))
 }
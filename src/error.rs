@@ -0,0 +1,106 @@
+use std::fmt;
+
+use geojson::feature::Id;
+
+// Maximum zoom level supported by the tile ids
+pub const MAX_ZOOM: u8 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeometryError {
+    MissingGeometry,        // feature without geometry
+    InvalidPosition(usize), // position with the given number of coordinates
+    NonFiniteCoordinate,    // NaN or infinite coordinate
+    LatitudeOutOfRange(f64),
+    TooFewPoints(usize), // polygon ring with the given number of positions
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeometryError::MissingGeometry => write!(f, "feature has no geometry"),
+            GeometryError::InvalidPosition(len) => {
+                write!(f, "position has {len} coordinates, expected at least 2")
+            }
+            GeometryError::NonFiniteCoordinate => write!(f, "coordinate is not a finite number"),
+            GeometryError::LatitudeOutOfRange(lat) => {
+                write!(f, "latitude {lat} is outside of [-90, 90]")
+            }
+            GeometryError::TooFewPoints(len) => {
+                write!(f, "ring has {len} positions, expected at least 4")
+            }
+        }
+    }
+}
+
+// A feature that was dropped from the index by `ValidationPolicy::Report`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidFeature {
+    pub feature: usize, // index of the feature in the input
+    pub id: Option<Id>,
+    pub error: GeometryError,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    ZoomOutOfRange {
+        z: u8,
+        max_zoom: u8,
+    },
+    YOutOfRange {
+        z: u8,
+        y: u32,
+    },
+    InvalidGeometry {
+        feature: usize, // index of the feature in the input
+        error: GeometryError,
+    },
+    ClusterNotFound {
+        cluster_id: u64,
+    },
+    NotEditable,             // edit of an index built without the `editable` option
+    InvalidBounds([f64; 4]), // custom bounds which are empty, inverted or not finite
+    GridMismatch {
+        layer: String, // layer whose projection has another tile grid than the other layers
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ZoomOutOfRange { z, max_zoom } => {
+                write!(f, "zoom {z} is higher than max zoom {max_zoom}")
+            }
+            Error::YOutOfRange { z, y } => {
+                write!(f, "tile y {y} is out of range at zoom {z}")
+            }
+            Error::InvalidGeometry { feature, error } => {
+                write!(f, "invalid geometry of feature {feature}: {error}")
+            }
+            Error::ClusterNotFound { cluster_id } => {
+                write!(f, "no cluster with id {cluster_id}")
+            }
+            Error::NotEditable => write!(f, "index was built without the editable option"),
+            Error::InvalidBounds([min_x, min_y, max_x, max_y]) => {
+                write!(f, "invalid bounds {min_x}, {min_y}, {max_x}, {max_y}")
+            }
+            Error::GridMismatch { layer } => {
+                write!(
+                    f,
+                    "layer {layer} has another tile grid than the other layers"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub(crate) fn check_tile(z: u8, y: u32, max_zoom: u8) -> Result<(), Error> {
+    if z > max_zoom {
+        return Err(Error::ZoomOutOfRange { z, max_zoom });
+    }
+    if y >= 1u32 << z {
+        return Err(Error::YOutOfRange { z, y });
+    }
+    Ok(())
+}
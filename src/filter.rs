@@ -0,0 +1,225 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use geojson::feature::Id;
+use geojson::{Geometry, JsonObject, JsonValue, Value};
+
+#[derive(Debug)]
+pub enum FilterError {
+    Json(serde_json::Error),
+    UnknownOperator(String),
+    InvalidFilter(JsonValue), // filter or part of it with missing or wrong operands
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Json(error) => write!(f, "failed to parse filter: {error}"),
+            FilterError::UnknownOperator(operator) => {
+                write!(f, "unknown filter operator {operator:?}")
+            }
+            FilterError::InvalidFilter(filter) => write!(f, "invalid filter {filter}"),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FilterError::Json(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Key {
+    Type, // `$type`, the geometry type without `Multi`
+    Id,   // `$id`
+    Property(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Compare(Key, Comparison, JsonValue),
+    In(Key, Vec<JsonValue>),
+    NotIn(Key, Vec<JsonValue>),
+    Has(Key),
+    NotHas(Key),
+    All(Vec<Expr>),
+    Any(Vec<Expr>),
+    None(Vec<Expr>),
+}
+
+// Feature filter in the legacy Mapbox GL filter syntax, e.g.
+// `["all", ["==", "$type", "LineString"], ["in", "class", "primary", "secondary"]]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    json: JsonValue,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn from_json(json: &JsonValue) -> Result<Self, FilterError> {
+        Ok(Self {
+            json: json.clone(),
+            expr: parse(json)?,
+        })
+    }
+
+    pub fn json(&self) -> &JsonValue {
+        &self.json
+    }
+
+    pub(crate) fn matches(
+        &self,
+        geometry: Option<&Geometry>,
+        properties: Option<&JsonObject>,
+        id: Option<&Id>,
+    ) -> bool {
+        let feature = FeatureValues {
+            geometry,
+            properties,
+            id,
+        };
+        feature.matches(&self.expr)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_json(&serde_json::from_str(s).map_err(FilterError::Json)?)
+    }
+}
+
+fn parse(json: &JsonValue) -> Result<Expr, FilterError> {
+    let invalid = || FilterError::InvalidFilter(json.clone());
+    let Some((operator, operands)) = json.as_array().and_then(|array| array.split_first()) else {
+        return Err(invalid());
+    };
+    let Some(operator) = operator.as_str() else {
+        return Err(invalid());
+    };
+    let key = || match operands.first().and_then(JsonValue::as_str) {
+        Some("$type") => Ok(Key::Type),
+        Some("$id") => Ok(Key::Id),
+        Some(name) => Ok(Key::Property(name.to_string())),
+        None => Err(invalid()),
+    };
+    let compare = |comparison| match operands {
+        [_, value] => Ok(Expr::Compare(key()?, comparison, value.clone())),
+        _ => Err(invalid()),
+    };
+    let filters = || operands.iter().map(parse).collect::<Result<Vec<_>, _>>();
+
+    Ok(match operator {
+        "==" => compare(Comparison::Equal)?,
+        "!=" => compare(Comparison::NotEqual)?,
+        "<" => compare(Comparison::Less)?,
+        "<=" => compare(Comparison::LessOrEqual)?,
+        ">" => compare(Comparison::Greater)?,
+        ">=" => compare(Comparison::GreaterOrEqual)?,
+        "in" => Expr::In(key()?, operands[1..].to_vec()),
+        "!in" => Expr::NotIn(key()?, operands[1..].to_vec()),
+        "has" if operands.len() == 1 => Expr::Has(key()?),
+        "!has" if operands.len() == 1 => Expr::NotHas(key()?),
+        "has" | "!has" => return Err(invalid()),
+        "all" => Expr::All(filters()?),
+        "any" => Expr::Any(filters()?),
+        "none" => Expr::None(filters()?),
+        operator => return Err(FilterError::UnknownOperator(operator.to_string())),
+    })
+}
+
+struct FeatureValues<'a> {
+    geometry: Option<&'a Geometry>, // features are filtered before they are validated
+    properties: Option<&'a JsonObject>,
+    id: Option<&'a Id>,
+}
+
+impl FeatureValues<'_> {
+    fn value(&self, key: &Key) -> Option<Cow<'_, JsonValue>> {
+        match key {
+            Key::Type => Some(Cow::Owned(JsonValue::from(match self.geometry?.value {
+                Value::Point(_) | Value::MultiPoint(_) => "Point",
+                Value::LineString(_) | Value::MultiLineString(_) => "LineString",
+                Value::Polygon(_) | Value::MultiPolygon(_) => "Polygon",
+                Value::GeometryCollection(_) => "Unknown",
+            }))),
+            Key::Id => self.id.map(|id| {
+                Cow::Owned(match id {
+                    Id::String(id) => JsonValue::from(id.as_str()),
+                    Id::Number(id) => JsonValue::Number(id.clone()),
+                })
+            }),
+            Key::Property(name) => self.properties?.get(name).map(Cow::Borrowed),
+        }
+    }
+
+    fn matches(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Compare(key, comparison, expected) => {
+                let Some(value) = self.value(key) else {
+                    return *comparison == Comparison::NotEqual;
+                };
+                match comparison {
+                    Comparison::Equal => equal(&value, expected),
+                    Comparison::NotEqual => !equal(&value, expected),
+                    Comparison::Less => compare(&value, expected) == Some(Ordering::Less),
+                    Comparison::LessOrEqual => matches!(
+                        compare(&value, expected),
+                        Some(Ordering::Less | Ordering::Equal)
+                    ),
+                    Comparison::Greater => compare(&value, expected) == Some(Ordering::Greater),
+                    Comparison::GreaterOrEqual => matches!(
+                        compare(&value, expected),
+                        Some(Ordering::Greater | Ordering::Equal)
+                    ),
+                }
+            }
+            Expr::In(key, values) => self.is_in(key, values),
+            Expr::NotIn(key, values) => !self.is_in(key, values),
+            Expr::Has(key) => self.value(key).is_some(),
+            Expr::NotHas(key) => self.value(key).is_none(),
+            Expr::All(exprs) => exprs.iter().all(|expr| self.matches(expr)),
+            Expr::Any(exprs) => exprs.iter().any(|expr| self.matches(expr)),
+            Expr::None(exprs) => !exprs.iter().any(|expr| self.matches(expr)),
+        }
+    }
+
+    fn is_in(&self, key: &Key, values: &[JsonValue]) -> bool {
+        self.value(key)
+            .is_some_and(|value| values.iter().any(|expected| equal(&value, expected)))
+    }
+}
+
+// numbers are equal by value, so `1` equals `1.0`
+fn equal(a: &JsonValue, b: &JsonValue) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// only numbers and strings are ordered, and only against values of the same type
+fn compare(a: &JsonValue, b: &JsonValue) -> Option<Ordering> {
+    match (a, b) {
+        (JsonValue::Number(a), JsonValue::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (JsonValue::String(a), JsonValue::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
@@ -0,0 +1,79 @@
+// Static KD-tree over points, ported from kdbush
+pub struct KdTree {
+    node_size: usize,
+    items: Vec<(usize, [f64; 2])>, // index of the point and its coordinates
+}
+
+impl KdTree {
+    pub fn new(points: impl IntoIterator<Item = [f64; 2]>, node_size: usize) -> Self {
+        let mut tree = Self {
+            node_size: node_size.max(1),
+            items: points.into_iter().enumerate().collect(),
+        };
+        if !tree.items.is_empty() {
+            tree.sort(0, tree.items.len() - 1, 0);
+        }
+        tree
+    }
+
+    // splits the items around the median of every level, alternating the axis
+    fn sort(&mut self, left: usize, right: usize, axis: usize) {
+        if right - left <= self.node_size {
+            return;
+        }
+        let m = (left + right) / 2;
+        self.items[left..=right]
+            .select_nth_unstable_by(m - left, |a, b| a.1[axis].total_cmp(&b.1[axis]));
+        self.sort(left, m - 1, 1 - axis);
+        self.sort(m + 1, right, 1 - axis);
+    }
+
+    // Indexes of the points inside the box
+    pub fn range(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Vec<usize> {
+        let min = [min_x, min_y];
+        let max = [max_x, max_y];
+        self.search(min, max, |p| {
+            p[0] >= min_x && p[0] <= max_x && p[1] >= min_y && p[1] <= max_y
+        })
+    }
+
+    // Indexes of the points within distance `r` of the point
+    pub fn within(&self, x: f64, y: f64, r: f64) -> Vec<usize> {
+        let r2 = r * r;
+        self.search([x - r, y - r], [x + r, y + r], |p| {
+            (p[0] - x).powi(2) + (p[1] - y).powi(2) <= r2
+        })
+    }
+
+    fn search(&self, min: [f64; 2], max: [f64; 2], keep: impl Fn(&[f64; 2]) -> bool) -> Vec<usize> {
+        let mut result = Vec::new();
+        if self.items.is_empty() {
+            return result;
+        }
+
+        let mut stack = vec![(0, self.items.len() - 1, 0)];
+        while let Some((left, right, axis)) = stack.pop() {
+            if right - left <= self.node_size {
+                for (i, p) in &self.items[left..=right] {
+                    if keep(p) {
+                        result.push(*i);
+                    }
+                }
+                continue;
+            }
+
+            let m = (left + right) / 2;
+            let (i, p) = &self.items[m];
+            if keep(p) {
+                result.push(*i);
+            }
+            if min[axis] <= p[axis] {
+                stack.push((left, m - 1, 1 - axis));
+            }
+            if max[axis] >= p[axis] {
+                stack.push((m + 1, right, 1 - axis));
+            }
+        }
+        result
+    }
+}
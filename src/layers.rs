@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use geojson::{Feature, FeatureCollection};
+
+use crate::error::{check_tile, Error, MAX_ZOOM};
+use crate::tile::Tile;
+use crate::{GeoJSONVT, Options};
+
+pub struct TileLayer<'a> {
+    pub name: &'a str,
+    pub extent: u16,
+    pub tile: Arc<Tile>,
+}
+
+// A tile of a layered index, containing the non-empty layers in the order they were added
+pub struct LayeredTile<'a> {
+    pub layers: Vec<TileLayer<'a>>,
+}
+
+impl LayeredTile<'_> {
+    pub fn layer(&self, name: &str) -> Option<&Tile> {
+        self.layers
+            .iter()
+            .find(|layer| layer.name == name)
+            .map(|layer| layer.tile.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+// Several named indexes that are sliced independently and queried together
+#[derive(Default)]
+pub struct LayeredGeoJSONVT {
+    layers: Vec<(String, GeoJSONVT)>,
+}
+
+impl LayeredGeoJSONVT {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a layer with its own options, replacing an existing layer with the same name. The
+    // projections of all layers have to share the tile grid, see `Projection::zoom_offset`.
+    pub fn add_layer(
+        &mut self,
+        name: &str,
+        features: &FeatureCollection,
+        options: &Options,
+    ) -> Result<(), Error> {
+        self.insert_layer(name, GeoJSONVT::new(features, options)?)
+    }
+
+    fn insert_layer(&mut self, name: &str, index: GeoJSONVT) -> Result<(), Error> {
+        let zoom_offset = index.options.projection.zoom_offset();
+        if self
+            .layers
+            .iter()
+            .any(|(n, layer)| n != name && layer.options.projection.zoom_offset() != zoom_offset)
+        {
+            return Err(Error::GridMismatch {
+                layer: name.to_string(),
+            });
+        }
+        match self.layers.iter_mut().find(|(n, _)| n == name) {
+            Some((_, layer)) => *layer = index,
+            None => self.layers.push((name.to_string(), index)),
+        }
+        Ok(())
+    }
+
+    // Assigns every feature to the layer returned by `layer_of`, features without a layer are
+    // dropped. All layers created this way share the same options.
+    pub fn add_layers_by<F>(
+        &mut self,
+        features: &FeatureCollection,
+        options: &Options,
+        layer_of: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&Feature) -> Option<String>,
+    {
+        let mut grouped: Vec<(String, Vec<&Feature>)> = Vec::new();
+        for feature in &features.features {
+            let Some(name) = layer_of(feature) else {
+                continue;
+            };
+            match grouped.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, features)) => features.push(feature),
+                None => grouped.push((name, vec![feature])),
+            }
+        }
+
+        for (name, features) in grouped {
+            self.insert_layer(&name, GeoJSONVT::from_features(features, options)?)?;
+        }
+        Ok(())
+    }
+
+    // Assigns features to layers by the string value of a property
+    pub fn add_layers_by_property(
+        &mut self,
+        features: &FeatureCollection,
+        options: &Options,
+        property: &str,
+    ) -> Result<(), Error> {
+        self.add_layers_by(features, options, |feature| {
+            feature
+                .property(property)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        })
+    }
+
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        self.layers.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&GeoJSONVT> {
+        self.layers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, index)| index)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut GeoJSONVT> {
+        self.layers
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .map(|(_, index)| index)
+    }
+
+    // Layers whose max zoom is lower than `z` are left out of the tile. The layers share the
+    // rows of the grid, its columns wrap in every layer.
+    pub fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<LayeredTile<'_>, Error> {
+        check_tile(z, y, MAX_ZOOM)?;
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for (name, index) in &self.layers {
+            if z > index.options.max_zoom {
+                continue;
+            }
+            let extent = index.options.tile.extent;
+            let tile = index.get_tile(z, x, y)?;
+            if !tile.features.features.is_empty() {
+                layers.push(TileLayer {
+                    name: name.as_str(),
+                    extent,
+                    tile,
+                });
+            }
+        }
+        Ok(LayeredTile { layers })
+    }
+}
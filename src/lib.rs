@@ -1,32 +1,124 @@
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
+use geojson::feature::Id;
 use geojson::{
-    Feature, FeatureCollection, GeoJson, Geometry, LineStringType, PointType, PolygonType,
+    Feature, FeatureCollection, GeoJson, Geometry, JsonObject, LineStringType, PointType,
+    PolygonType,
 };
 
 use crate::clip::clip;
-use crate::convert::convert;
-use crate::tile::{InternalTile, Tile, EMPTY_TILE};
+use crate::convert::{convert, convert_feature, Project};
+use crate::error::check_tile;
+use crate::tile::{InternalTile, EMPTY_TILE};
 use crate::types::*;
 use crate::wrap::wrap;
 
 mod clip;
+mod cluster;
+mod compact;
 mod convert;
+mod error;
+mod filter;
+mod kdtree;
+mod layers;
+#[cfg(feature = "mbtiles")]
+pub mod mbtiles;
+#[cfg(feature = "mvt")]
+pub mod mvt;
+mod persist;
+#[cfg(feature = "pmtiles")]
+pub mod pmtiles;
+mod projection;
 mod simplify;
+mod stats;
+mod stream;
 mod tile;
 mod types;
 mod wrap;
 
+// the ported tests are kept as close as possible to the upstream ones
 #[cfg(test)]
+#[allow(
+    unused_imports,
+    clippy::bool_assert_comparison,
+    clippy::for_kv_map,
+    clippy::get_first,
+    clippy::needless_borrow,
+    clippy::needless_return
+)]
 mod tests;
 
+pub use cluster::{Aggregate, ClusterOptions, ClusterProperty, Supercluster};
+pub use compact::{CompactFeature, CompactGeometry, CompactTile, TilePoint};
+pub use convert::SourceFeature;
+pub use error::{Error, GeometryError, InvalidFeature, MAX_ZOOM};
+pub use filter::{Filter, FilterError};
+pub use layers::{LayeredGeoJSONVT, LayeredTile, TileLayer};
+pub use persist::PersistError;
+pub use projection::{CustomBounds, Equirectangular, Projection, WebMercator};
+pub use stats::{IndexStats, ZoomStats};
+pub use stream::StreamError;
+pub use tile::Tile;
+
 #[derive(Clone)]
 pub struct TileOptions {
-    pub tolerance: f64,     // simplification tolerance (higher means simpler)
-    pub extent: u16,        // tile extent
-    pub buffer: u16,        // tile buffer on each side
+    pub tolerance: f64,             // simplification tolerance (higher means simpler)
+    pub extent: u16,                // tile extent
+    pub buffer: u16,                // tile buffer on each side
     pub line_metrics: bool, // enable line metrics tracking for LineString/MultiLineString features
+    pub clean_polygons: bool, // rewind polygon rings for vector tiles and drop collapsed rings
+    pub properties: PropertyFilter, // properties of the features kept in the tiles
+    pub transform_properties: Option<Arc<PropertyTransformFn>>, // changes the properties per zoom
+    pub feature_predicate: Option<Arc<FeaturePredicateFn>>, // features shown in a tile, all if None
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum PropertyFilter {
+    #[default]
+    All,
+    Include(Vec<String>),
+    Exclude(Vec<String>),
+}
+
+impl PropertyFilter {
+    pub(crate) fn apply(&self, properties: &mut JsonObject) {
+        match self {
+            PropertyFilter::All => {}
+            PropertyFilter::Include(names) => properties.retain(|name, _| names.contains(name)),
+            PropertyFilter::Exclude(names) => properties.retain(|name, _| !names.contains(name)),
+        }
+    }
+}
+
+// Called with the zoom and the filtered properties of every feature of a tile
+pub type PropertyTransformFn = dyn Fn(u8, &mut JsonObject) + Send + Sync;
+
+// Called with the zoom, the properties and the geometry type of every feature of a tile
+pub type FeaturePredicateFn = dyn Fn(u8, &JsonObject, GeometryType) -> bool + Send + Sync;
+
+// Multi geometries have the type of their parts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeometryType {
+    Point,
+    LineString,
+    Polygon,
+    GeometryCollection,
+}
+
+impl GeometryType {
+    pub(crate) fn of(geometry: &VtGeometry) -> Self {
+        match geometry {
+            VtGeometry::Point(_) | VtGeometry::MultiPoint(_) => GeometryType::Point,
+            VtGeometry::LineString(_) | VtGeometry::MultiLineString(_) => GeometryType::LineString,
+            VtGeometry::Polygon(_) | VtGeometry::MultiPolygon(_) => GeometryType::Polygon,
+            VtGeometry::Empty(_) | VtGeometry::GeometryCollection(_) => {
+                GeometryType::GeometryCollection
+            }
+        }
+    }
 }
 
 impl Default for TileOptions {
@@ -36,6 +128,10 @@ impl Default for TileOptions {
             extent: 4096,
             buffer: 64,
             line_metrics: false,
+            clean_polygons: false,
+            properties: PropertyFilter::All,
+            transform_properties: None,
+            feature_predicate: None,
         }
     }
 }
@@ -46,6 +142,13 @@ pub struct Options {
     pub index_max_zoom: u8,    // max zoom in the tile index
     pub index_max_points: u32, // max number of points per tile in the tile index
     pub generate_id: bool,     // whether to generate feature ids, overriding existing ids
+    pub editable: bool,        // keep the converted features so the index can be edited
+    pub promote_id: Option<PromoteId>, // take feature ids from the properties, overrides generate_id
+    pub validation: ValidationPolicy,  // how to handle features with invalid geometries
+    pub cache_limit: Option<CacheLimit>, // limit of the tiles generated on request, unbounded if None
+    pub feature_zoom: Option<FeatureZoom>, // zoom range of every feature, all zooms if None
+    pub filter: Option<Filter>,          // features which are indexed, all if None
+    pub projection: Arc<dyn Projection>, // maps input coordinates to the tile grid
     pub tile: TileOptions,
 }
 
@@ -56,36 +159,75 @@ impl Default for Options {
             index_max_zoom: 5,
             index_max_points: 100000,
             generate_id: false,
+            editable: false,
+            promote_id: None,
+            validation: ValidationPolicy::default(),
+            cache_limit: None,
+            feature_zoom: None,
+            filter: None,
+            projection: Arc::new(WebMercator),
             tile: TileOptions::default(),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    // silently drop invalid features
+    #[default]
+    Skip,
+    // abort with `Error::InvalidGeometry`
+    Fail,
+    // drop invalid features and keep them in `GeoJSONVT::invalid_features`
+    Report,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheLimit {
+    Tiles(usize), // number of tiles
+    Bytes(usize), // approximate heap bytes of the tiles
+}
+
+// Where the zoom range of a feature is read from. Missing or non-numeric values don't limit the
+// range.
+#[derive(Clone)]
+pub enum FeatureZoom {
+    // numeric properties of the feature
+    Properties { min_zoom: String, max_zoom: String },
+    // the `tippecanoe` member of the feature, e.g. `"tippecanoe": {"minzoom": 4, "maxzoom": 10}`
+    Tippecanoe,
+    // min and max zoom from the properties of the feature
+    Callback(Arc<ZoomRangeFn>),
+}
+
+pub type ZoomRangeFn = dyn Fn(&JsonObject) -> (u8, u8) + Send + Sync;
+
+// Where feature ids are taken from. Features without an id in their properties have none.
+#[derive(Clone)]
+pub enum PromoteId {
+    // a string or number property
+    Property(String),
+    // id from the properties of the feature
+    Callback(Arc<PromoteIdFn>),
+}
+
+pub type PromoteIdFn = dyn Fn(&JsonObject) -> Option<Id> + Send + Sync;
+
 fn to_id(z: u8, x: u32, y: u32) -> u64 {
     (((1u64 << z as u64) * y as u64 + x as u64) * 32) + z as u64
 }
 
-fn geojson_to_feature_collection(geojson: &GeoJson) -> FeatureCollection {
-    // TODO cleanup this conversion
-    match geojson {
-        GeoJson::Geometry(geom) => FeatureCollection {
-            bbox: None,
-            features: vec![Feature {
-                bbox: None,
-                geometry: Some(geom.clone()),
-                id: None,
-                properties: None,
-                foreign_members: None,
-            }],
-            foreign_members: None,
-        },
-        GeoJson::Feature(feature) => FeatureCollection {
-            bbox: None,
-            features: vec![feature.clone()],
-            foreign_members: None,
-        },
-        GeoJson::FeatureCollection(features) => features.clone(),
-    }
+// Features of a GeoJSON object, a bare geometry is a feature without properties
+fn geojson_features(geojson: &GeoJson) -> impl Iterator<Item = &dyn SourceFeature> {
+    let (features, geometry): (&[Feature], _) = match geojson {
+        GeoJson::Geometry(geometry) => (&[], Some(geometry)),
+        GeoJson::Feature(feature) => (std::slice::from_ref(feature), None),
+        GeoJson::FeatureCollection(collection) => (&collection.features, None),
+    };
+    features
+        .iter()
+        .map(|feature| feature as &dyn SourceFeature)
+        .chain(geometry.map(|geometry| geometry as &dyn SourceFeature))
 }
 
 pub fn geojson_to_tile(
@@ -96,14 +238,21 @@ pub fn geojson_to_tile(
     options: &TileOptions,
     wrap_: bool,
     clip_: bool,
-) -> Tile {
-    let features_ = &geojson_to_feature_collection(geojson);
+) -> Result<Tile, Error> {
+    check_tile(z, y, MAX_ZOOM)?;
+
     let z2 = 1u32 << z;
     let tolerance = (options.tolerance / options.extent as f64) / z2 as f64;
-    let mut features = convert(features_, tolerance, false);
+    // there is no way to return a report, so any invalid feature is an error
+    let convert_options = Options {
+        validation: ValidationPolicy::Fail,
+        tile: options.clone(),
+        ..Options::default()
+    };
+    let (mut features, _) = convert(geojson_features(geojson), tolerance, &convert_options)?;
     if wrap_ {
         features = wrap(
-            &features,
+            features,
             options.buffer as f64 / options.extent as f64,
             options.line_metrics,
         );
@@ -130,198 +279,723 @@ pub fn geojson_to_tile(
         );
     }
 
-    InternalTile::new(
-        &features,
-        z,
-        x,
-        y,
-        options.extent,
-        tolerance,
-        options.line_metrics,
-    )
-    .tile
+    Ok(InternalTile::new(&features, z, x, y, options, tolerance, z)
+        .compact
+        .to_geojson())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Split {
+    Index,                   // first-pass tiling of the index
+    Rebuild,                 // first-pass tiling after an edit, keeping the tiles still cached
+    DrillDown(u8, u32, u32), // drill down to the given tile
+}
+
+#[derive(Default)]
+struct TileStore {
+    tiles: HashMap<u64, InternalTile>,
+    cached: HashMap<u64, CachedTile>, // tiles which can be evicted
+    cached_bytes: usize,
+    clock: AtomicU64,
 }
 
+struct CachedTile {
+    last_used: AtomicU64,
+    bytes: usize,
+}
+
+impl TileStore {
+    // Adds the tiles of a disjoint subtree
+    #[cfg(feature = "rayon")]
+    fn extend(&mut self, other: TileStore) {
+        self.tiles.extend(other.tiles);
+    }
+
+    // Removes the tiles whose buffered bounds touch one of the changed bboxes. The bounds of a
+    // tile contain those of its children, so all ancestors of a removed tile are removed too.
+    fn invalidate(&mut self, changed: &[BBox], buffer: f64) {
+        self.tiles.retain(|_, tile| {
+            let z2 = (1u32 << tile.z) as f64;
+            let min = Point2D::new((tile.x as f64 - buffer) / z2, (tile.y as f64 - buffer) / z2);
+            let max = Point2D::new(
+                (tile.x as f64 + 1. + buffer) / z2,
+                (tile.y as f64 + 1. + buffer) / z2,
+            );
+            !changed.iter().any(|bbox| {
+                bbox.min.x <= max.x
+                    && bbox.max.x >= min.x
+                    && bbox.min.y <= max.y
+                    && bbox.max.y >= min.y
+            })
+        });
+
+        let tiles = &self.tiles;
+        let cached_bytes = &mut self.cached_bytes;
+        self.cached.retain(|id, cached| {
+            let kept = tiles.contains_key(id);
+            if !kept {
+                *cached_bytes -= cached.bytes;
+            }
+            kept
+        });
+    }
+
+    // Finds the closest ancestor containing the original geometry
+    fn find_parent(&self, z: u8, x: u32, y: u32) -> Option<&InternalTile> {
+        let mut z0 = z;
+        let mut x0 = x;
+        let mut y0 = y;
+
+        while z0 != 0 {
+            z0 -= 1;
+            x0 /= 2;
+            y0 /= 2;
+            match self.tiles.get(&to_id(z0, x0, y0)) {
+                Some(parent) if !parent.source_features.is_empty() => return Some(parent),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    // Marks a tile as used, only needs a read lock
+    fn touch(&self, id: u64) {
+        if let Some(cached) = self.cached.get(&id) {
+            let now = self.clock.fetch_add(1, Ordering::Relaxed);
+            cached.last_used.fetch_max(now, Ordering::Relaxed);
+        }
+    }
+
+    // Evicts the least recently used tiles once the limit is exceeded. To not sort the tiles on
+    // every drill down, a quarter of the limit is freed at once.
+    fn evict(&mut self, limit: CacheLimit) {
+        let size = |store: &TileStore| match limit {
+            CacheLimit::Tiles(_) => store.cached.len(),
+            CacheLimit::Bytes(_) => store.cached_bytes,
+        };
+        let (CacheLimit::Tiles(max) | CacheLimit::Bytes(max)) = limit;
+        if size(self) <= max {
+            return;
+        }
+
+        let mut tiles = self
+            .cached
+            .iter()
+            .map(|(id, cached)| (cached.last_used.load(Ordering::Relaxed), *id))
+            .collect::<Vec<_>>();
+        tiles.sort_unstable();
+        for (_, id) in tiles {
+            if size(self) <= max - max / 4 {
+                break;
+            }
+            self.tiles.remove(&id);
+            if let Some(cached) = self.cached.remove(&id) {
+                self.cached_bytes -= cached.bytes;
+            }
+        }
+    }
+
+    // Merges the tiles of a drill down. Tiles which were generated concurrently by another
+    // request are kept, but lose their source geometry if the drill down sliced them further.
+    fn merge(&mut self, tiles: HashMap<u64, InternalTile>, limit: Option<CacheLimit>) {
+        for (id, tile) in tiles {
+            match self.tiles.entry(id) {
+                Entry::Occupied(mut entry) => {
+                    if tile.source_features.is_empty() {
+                        entry.get_mut().source_features = Arc::default();
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    let tile = entry.insert(tile);
+                    if limit.is_some() {
+                        let bytes = stats::compact_size(&tile.compact);
+                        let last_used = AtomicU64::new(self.clock.fetch_add(1, Ordering::Relaxed));
+                        self.cached.insert(id, CachedTile { last_used, bytes });
+                        self.cached_bytes += bytes;
+                    }
+                }
+            }
+        }
+        if let Some(limit) = limit {
+            self.evict(limit);
+        }
+    }
+}
+
+// The index can be shared between threads. Tiles below the index are generated on request and
+// cached, requests for different tiles are sliced in parallel.
 pub struct GeoJSONVT {
     options: Options,
-    stats: HashMap<u8, u32>,
-    total: u32,
-    tiles: HashMap<u64, InternalTile>,
+    tiles: RwLock<TileStore>,
+    empty: Arc<Tile>,
+    invalid_features: Vec<InvalidFeature>,
+    source: VtFeatures, // wrapped features of the index, sliced again after edits; empty unless editable
+    bounds: Vec<FeatureBounds>, // bounds of the wrapped features, in the order of `source`
+    num_features: usize, // number of features added so far, valid or not
+}
+
+// What is left of a feature once it is sliced into the index
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FeatureBounds {
+    pub bbox: BBox,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+impl FeatureBounds {
+    fn of(feature: &VtFeature) -> Self {
+        Self {
+            bbox: feature.bbox,
+            min_zoom: feature.min_zoom,
+            max_zoom: feature.max_zoom,
+        }
+    }
+
+    fn in_zoom_range(&self, z: u8) -> bool {
+        self.min_zoom <= z && z <= self.max_zoom
+    }
 }
 
 impl GeoJSONVT {
-    pub fn from_geojson(geojson: &GeoJson, options: &Options) -> Self {
-        let collection = geojson_to_feature_collection(geojson);
-        Self::new(&collection, options)
+    pub fn from_geojson(geojson: &GeoJson, options: &Options) -> Result<Self, Error> {
+        Self::build(geojson_features(geojson).map(Ok::<_, Error>), options)
     }
 
-    pub fn new(features_: &FeatureCollection, options: &Options) -> Self {
+    // Like `from_geojson`, but the input is dropped feature by feature as it is converted
+    pub fn from_owned(geojson: GeoJson, options: &Options) -> Result<Self, Error> {
+        match geojson {
+            GeoJson::Geometry(geometry) => Self::build([Ok::<_, Error>(geometry)], options),
+            GeoJson::Feature(feature) => Self::build([Ok::<_, Error>(feature)], options),
+            GeoJson::FeatureCollection(collection) => {
+                Self::build(collection.features.into_iter().map(Ok::<_, Error>), options)
+            }
+        }
+    }
+
+    pub fn new(features: &FeatureCollection, options: &Options) -> Result<Self, Error> {
+        Self::build(features.features.iter().map(Ok::<_, Error>), options)
+    }
+
+    // Converts the features one at a time as they arrive, so only the projected data is kept
+    pub(crate) fn build<F: SourceFeature, E: From<Error>>(
+        features: impl IntoIterator<Item = Result<F, E>>,
+        options: &Options,
+    ) -> Result<Self, E> {
+        if options.max_zoom > MAX_ZOOM {
+            return Err(Error::ZoomOutOfRange {
+                z: options.max_zoom,
+                max_zoom: MAX_ZOOM,
+            }
+            .into());
+        }
+
         let mut vt = Self {
             options: options.clone(),
-            stats: HashMap::default(),
-            total: 0,
-            tiles: HashMap::default(),
+            tiles: RwLock::default(),
+            empty: Arc::new(EMPTY_TILE.clone()),
+            invalid_features: Vec::new(),
+            source: Vec::new(),
+            bounds: Vec::new(),
+            num_features: 0,
         };
 
-        let z2 = 1u32 << options.max_zoom;
+        let mut converted = Vec::new();
+        for feature in features {
+            converted.extend(convert_feature(
+                feature?,
+                vt.num_features,
+                vt.convert_tolerance(),
+                options,
+                &mut vt.invalid_features,
+            )?);
+            vt.num_features += 1;
+        }
+        vt.source = vt.wrap(converted);
 
-        let converted = convert(
-            features_,
-            (options.tile.tolerance / options.tile.extent as f64) / z2 as f64,
-            options.generate_id,
-        );
+        let mut store = TileStore::default();
+        vt.split_tile(&mut store, &vt.source, 0, 0, 0, Split::Index);
+        vt.tiles = RwLock::new(store);
+        vt.bounds = vt.source.iter().map(FeatureBounds::of).collect();
+        if !options.editable {
+            vt.source = Vec::new();
+        }
+        Ok(vt)
+    }
+
+    // Zoom of the index holding zoom `z` of the tile grid, see `Projection::zoom_offset`
+    pub(crate) fn index_zoom(&self, z: u8) -> u8 {
+        z + self.options.projection.zoom_offset()
+    }
+
+    // Zoom of the tile grid of an index tile, tiles above the root of the grid count as zoom 0
+    pub(crate) fn grid_zoom(&self, z: u8) -> u8 {
+        z.saturating_sub(self.options.projection.zoom_offset())
+    }
 
-        let features = wrap(
-            &converted,
-            options.tile.buffer as f64 / options.tile.extent as f64,
-            options.tile.line_metrics,
+    fn convert_tolerance(&self) -> f64 {
+        let z2 = 1u32 << self.index_zoom(self.options.max_zoom);
+        (self.options.tile.tolerance / self.options.tile.extent as f64) / z2 as f64
+    }
+
+    fn tile_tolerance(&self, z: u8) -> f64 {
+        if z == self.index_zoom(self.options.max_zoom) {
+            0.
+        } else {
+            self.options.tile.tolerance / ((1u32 << z) as f64 * self.options.tile.extent as f64)
+        }
+    }
+
+    fn wrap(&self, features: VtFeatures) -> VtFeatures {
+        if !self.options.projection.wraps() {
+            // grids which don't wrap only hold what is within them
+            let line_metrics = self.options.tile.line_metrics;
+            let features = clip::<0>(&features, 0., 1., -1., 2., line_metrics);
+            return clip::<1>(&features, 0., 1., -1., 2., line_metrics);
+        }
+        wrap(
+            features,
+            self.options.tile.buffer as f64 / self.options.tile.extent as f64,
+            self.options.tile.line_metrics,
+        )
+    }
+
+    // Adds a feature to the index. Invalid geometries are handled like in `new`, with the feature
+    // counted after the features added before it.
+    pub fn insert_feature(&mut self, feature: &Feature) -> Result<(), Error> {
+        self.check_editable()?;
+        let added = self.convert_feature(feature)?;
+        let changed: Vec<BBox> = added.iter().map(|feature| feature.bbox).collect();
+        self.bounds.extend(added.iter().map(FeatureBounds::of));
+        self.source.extend(added);
+        self.invalidate(&changed);
+        Ok(())
+    }
+
+    // Replaces the features with the given id, keeping their id and position. Returns false if
+    // no feature has the id. If the new geometry is invalid and skipped, the old one is removed.
+    pub fn update_feature(&mut self, id: &Id, feature: &Feature) -> Result<bool, Error> {
+        self.check_editable()?;
+        let Some(position) = self
+            .source
+            .iter()
+            .position(|feature| feature.id.as_deref() == Some(id))
+        else {
+            return Ok(false);
+        };
+
+        let mut added = self.convert_feature(feature)?;
+        let shared_id = Arc::new(id.clone());
+        for feature in &mut added {
+            feature.id = Some(shared_id.clone());
+        }
+
+        let mut changed = self.take_features(id);
+        changed.extend(added.iter().map(|feature| feature.bbox));
+        self.bounds.splice(
+            position..position,
+            added.iter().map(FeatureBounds::of).collect::<Vec<_>>(),
         );
+        self.source.splice(position..position, added);
+        self.invalidate(&changed);
+        Ok(true)
+    }
 
-        vt.split_tile_zeros(&features, 0, 0, 0);
-        vt
+    // Removes the features with the given id, returns false if there are none
+    pub fn remove_feature(&mut self, id: &Id) -> Result<bool, Error> {
+        self.check_editable()?;
+        let changed = self.take_features(id);
+        self.invalidate(&changed);
+        Ok(!changed.is_empty())
     }
 
-    pub fn get_tile(&mut self, z: u8, x_: u32, y: u32) -> &Tile {
-        if z > self.options.max_zoom {
-            panic!("Requested zoom higher than maxZoom: {}", z);
+    fn check_editable(&self) -> Result<(), Error> {
+        if !self.options.editable {
+            return Err(Error::NotEditable);
         }
+        Ok(())
+    }
 
+    fn convert_feature(&mut self, feature: &Feature) -> Result<VtFeatures, Error> {
+        let converted = convert_feature(
+            feature,
+            self.num_features,
+            self.convert_tolerance(),
+            &self.options,
+            &mut self.invalid_features,
+        )?;
+        self.num_features += 1;
+        Ok(match converted {
+            Some(converted) => self.wrap(vec![converted]),
+            None => Vec::new(),
+        })
+    }
+
+    // Removes the features with the given id, returning their bboxes
+    fn take_features(&mut self, id: &Id) -> Vec<BBox> {
+        let mut changed = Vec::new();
+        for i in (0..self.source.len()).rev() {
+            if self.source[i].id.as_deref() == Some(id) {
+                changed.push(self.source.remove(i).bbox);
+                self.bounds.remove(i);
+            }
+        }
+        changed
+    }
+
+    // Drops the cached tiles touched by the changed bboxes and slices them again from the root.
+    // Untouched tiles are kept together with the tiles drilled down from them, and only the
+    // quadrants of dropped tiles are clipped.
+    fn invalidate(&mut self, changed: &[BBox]) {
+        if changed.is_empty() {
+            return;
+        }
+
+        let tiles = self.tiles.get_mut().unwrap_or_else(|e| e.into_inner());
+        let mut store = std::mem::take(tiles);
+        store.invalidate(
+            changed,
+            self.options.tile.buffer as f64 / self.options.tile.extent as f64,
+        );
+        self.split_tile(&mut store, &self.source, 0, 0, 0, Split::Rebuild);
+        *self.tiles.get_mut().unwrap_or_else(|e| e.into_inner()) = store;
+    }
+
+    // A poisoned lock can't leave the cache inconsistent, as every insertion is a single map
+    // operation, so the poison is ignored.
+    fn read_tiles(&self) -> RwLockReadGuard<'_, TileStore> {
+        self.tiles.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write_tiles(&self) -> RwLockWriteGuard<'_, TileStore> {
+        self.tiles.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Arc<Tile>, Error> {
+        // the byte limit only counts the compact tiles, so their views are created per request
+        let tile = match self.options.cache_limit {
+            Some(CacheLimit::Bytes(_)) => {
+                self.find_tile(z, x, y, |tile| Arc::new(tile.compact.to_geojson()))?
+            }
+            _ => self.find_tile(z, x, y, InternalTile::tile)?,
+        };
+        Ok(tile.unwrap_or_else(|| self.empty.clone()))
+    }
+
+    // Like `get_tile`, with integer coordinates and without creating the GeoJSON view
+    pub fn get_tile_compact(&self, z: u8, x: u32, y: u32) -> Result<Arc<CompactTile>, Error> {
+        let tile = self.find_tile(z, x, y, |tile| tile.compact.clone())?;
+        Ok(tile.unwrap_or_default())
+    }
+
+    // Looks up the tile or drills down to it, `None` for tiles without features
+    fn find_tile<T>(
+        &self,
+        z: u8,
+        x_: u32,
+        y: u32,
+        view: impl Fn(&InternalTile) -> T,
+    ) -> Result<Option<T>, Error> {
+        check_tile(z, y, self.options.max_zoom)?;
+
+        let z = self.index_zoom(z);
         let z2 = 1u32 << z;
         let x = ((x_ % z2) + z2) % z2; // wrap tile x coordinate
         let id = to_id(z, x, y);
 
-        if self.tiles.contains_key(&id) {
-            return &self.tiles[&id].tile;
-        }
+        let parent = {
+            let tiles = self.read_tiles();
+            if let Some(tile) = tiles.tiles.get(&id) {
+                tiles.touch(id);
+                return Ok(Some(view(tile)));
+            }
 
-        if let Some(parent) = self.find_parent(z, x, y) {
             // if we found a parent tile containing the original geometry, we can drill down from it
+            match tiles.find_parent(z, x, y) {
+                Some(parent) => parent.clone(),
+                None => return Ok(None),
+            }
+        };
 
-            // drill down parent tile up to the requested one
-            self.split_tile(
-                &parent.source_features.clone(),
-                parent.z,
-                parent.x,
-                parent.y,
-                z,
-                x,
-                y,
-            );
+        // drill down parent tile up to the requested one without blocking other requests
+        let source_features = parent.source_features.clone();
+        let (pz, px, py) = (parent.z, parent.x, parent.y);
+        let mut drilldown = TileStore::default();
+        drilldown.tiles.insert(to_id(pz, px, py), parent);
+        self.split_tile(
+            &mut drilldown,
+            &source_features,
+            pz,
+            px,
+            py,
+            Split::DrillDown(z, x, y),
+        );
 
-            if self.tiles.contains_key(&id) {
-                return &self.tiles[&id].tile;
-            }
+        let tile = drilldown.tiles.get(&id).map(view);
+        self.write_tiles()
+            .merge(drilldown.tiles, self.options.cache_limit);
+        Ok(tile)
+    }
 
-            if self.find_parent(z, x, y).is_none() {
-                panic!("Parent tile not found");
-            }
-        } else {
-            panic!("Parent tile not found");
+    // Features dropped because of invalid geometries, only filled with `ValidationPolicy::Report`
+    pub fn invalid_features(&self) -> &[InvalidFeature] {
+        &self.invalid_features
+    }
+
+    // Bounds of all features as west, south, east and north in degrees, clamped to the grid.
+    // `None` if the projection doesn't give degrees.
+    #[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+    pub(crate) fn bounds(&self) -> Option<[f64; 4]> {
+        let projection = &self.options.projection;
+        if !projection.geographic() {
+            return None;
+        }
+        let mut bounds = BBox::new(Point2D::new(1., 1.), Point2D::new(0., 0.));
+        for feature in &self.bounds {
+            bounds.min.x = bounds.min.x.min(feature.bbox.min.x);
+            bounds.min.y = bounds.min.y.min(feature.bbox.min.y);
+            bounds.max.x = bounds.max.x.max(feature.bbox.max.x);
+            bounds.max.y = bounds.max.y.max(feature.bbox.max.y);
         }
+        if self.bounds.is_empty() {
+            bounds = BBox::new(Point2D::new(0., 0.), Point2D::new(1., 1.));
+        }
+        let min = projection.unproject(bounds.min.x.max(0.), bounds.max.y.min(1.));
+        let max = projection.unproject(bounds.max.x.min(1.), bounds.min.y.max(0.));
+        Some([min[0], min[1], max[0], max[1]])
+    }
 
-        &EMPTY_TILE
+    // Visits the tiles of the grid up to `max_zoom` which contain source geometry. Tiles without
+    // any source points can't have children with geometry, so their subtrees are skipped instead
+    // of requesting every tile of the grid. Tiles which aren't cached are drilled down into a
+    // scratch store that only holds the subtrees being walked, the tile cache isn't changed.
+    #[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+    pub(crate) fn for_each_tile<E, F>(&self, max_zoom: u8, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(u8, u32, u32, &Tile) -> Result<(), E>,
+    {
+        let offset = self.options.projection.zoom_offset();
+        let max_zoom = self.index_zoom(max_zoom.min(self.options.max_zoom));
+        let mut scratch = TileStore::default();
+        // the second value marks the end of a subtree, once it is walked its root is dropped
+        let mut stack = vec![((0u8, 0u32, 0u32), false)];
+        while let Some(((z, x, y), walked)) = stack.pop() {
+            if walked {
+                scratch.tiles.remove(&to_id(z, x, y));
+                continue;
+            }
+            let Some((tile, hidden_features)) = self.walk_tile(&mut scratch, z, x, y) else {
+                continue;
+            };
+            stack.push(((z, x, y), true));
+            if tile.num_points == 0 && hidden_features == 0 {
+                continue;
+            }
+            // the GeoJSON views are not cached, so walking all tiles doesn't double the memory
+            if tile.num_points > 0 && z >= offset {
+                f(z - offset, x, y, &tile.to_geojson())?;
+            }
+            if z < max_zoom {
+                for (dx, dy) in [(1, 1), (0, 1), (1, 0), (0, 0)] {
+                    stack.push(((z + 1, x * 2 + dx, y * 2 + dy), false));
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub(crate) fn get_internal_tiles(&self) -> &HashMap<u64, InternalTile> {
-        &self.tiles
+    // Like `find_tile`, but drills down into `scratch` instead of the tile cache
+    #[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+    fn walk_tile(
+        &self,
+        scratch: &mut TileStore,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> Option<(Arc<CompactTile>, u32)> {
+        let id = to_id(z, x, y);
+        let view = |tile: &InternalTile| (tile.compact.clone(), tile.hidden_features);
+        if let Some(tile) = scratch.tiles.get(&id) {
+            return Some(view(tile));
+        }
+
+        let parent = match scratch.find_parent(z, x, y) {
+            Some(parent) => (parent.z, parent.x, parent.y),
+            None => {
+                let tiles = self.read_tiles();
+                if let Some(tile) = tiles.tiles.get(&id) {
+                    return Some(view(tile));
+                }
+                let parent = tiles.find_parent(z, x, y)?.clone();
+                let coordinates = (parent.z, parent.x, parent.y);
+                scratch
+                    .tiles
+                    .insert(to_id(parent.z, parent.x, parent.y), parent);
+                coordinates
+            }
+        };
+
+        let (pz, px, py) = parent;
+        let source_features = scratch.tiles[&to_id(pz, px, py)].source_features.clone();
+        self.split_tile(
+            scratch,
+            &source_features,
+            pz,
+            px,
+            py,
+            Split::DrillDown(z, x, y),
+        );
+        scratch.tiles.get(&id).map(view)
     }
 
-    fn find_parent(&self, z: u8, x: u32, y: u32) -> Option<&InternalTile> {
-        let mut z0 = z;
-        let mut x0 = x;
-        let mut y0 = y;
+    // Walks all cached tiles, so this takes about as long as cloning the index
+    pub fn stats(&self) -> IndexStats {
+        let mut seen = HashSet::new();
+        let mut stats = IndexStats {
+            feature_bytes: stats::features_size(&self.source, &mut seen)
+                + self.bounds.len() * size_of::<FeatureBounds>(),
+            ..IndexStats::default()
+        };
+        let offset = self.options.projection.zoom_offset();
+        for tile in self.read_tiles().tiles.values() {
+            stats.add_tile(tile, tile.z.checked_sub(offset), &mut seen);
+        }
+        stats
+    }
 
-        let end = None;
-        let mut parent = end;
+    // Coordinates of the generated tiles which contain features, ordered by zoom, x and y. Tiles
+    // generated by later `get_tile` calls show up in later calls.
+    pub fn generated_tiles(&self) -> impl Iterator<Item = (u8, u32, u32)> {
+        let offset = self.options.projection.zoom_offset();
+        let mut tiles = self
+            .read_tiles()
+            .tiles
+            .values()
+            .filter(|tile| !tile.compact.features.is_empty())
+            .filter_map(|tile| Some((tile.z.checked_sub(offset)?, tile.x, tile.y)))
+            .collect::<Vec<_>>();
+        tiles.sort_unstable();
+        tiles.into_iter()
+    }
 
-        while (parent == end) && (z0 != 0) {
-            z0 -= 1;
-            x0 /= 2;
-            y0 /= 2;
-            parent = self.tiles.get(&to_id(z0, x0, y0));
+    // Tiles at zoom `z` intersected by the bbox of a feature, including the tile buffer, ordered
+    // by x and y. These are the only tiles which can contain features. `bbox` restricts the
+    // result to the tiles intersecting west, south, east and north in the coordinates of the
+    // projection, i.e. degrees by default.
+    pub fn covering_tiles(
+        &self,
+        z: u8,
+        bbox: Option<[f64; 4]>,
+    ) -> Result<Vec<(u8, u32, u32)>, Error> {
+        if z > self.options.max_zoom {
+            return Err(Error::ZoomOutOfRange {
+                z,
+                max_zoom: self.options.max_zoom,
+            });
         }
 
-        parent
+        // columns of the index zoom, rows of the grid
+        let z2 = (1u32 << self.index_zoom(z)) as f64;
+        let (max_x, max_y) = ((1u32 << self.index_zoom(z)) - 1, (1u32 << z) - 1);
+        let project = Project {
+            tolerance: 0.,
+            projection: self.options.projection.as_ref(),
+        };
+        let limit = match bbox {
+            Some([west, south, east, north]) => {
+                let min = project.project_point(&[west, north]);
+                let max = project.project_point(&[east, south]);
+                BBox::new(Point2D::new(min.x, min.y), Point2D::new(max.x, max.y))
+            }
+            None => BBox::new(Point2D::new(0., 0.), Point2D::new(1., 1.)),
+        };
+        // tile range of a range in projected coordinates, extended by `buffer` tiles
+        let range = |min: f64, max_: f64, buffer: f64, max: u32| {
+            let first = (min * z2 - 1. - buffer).ceil().max(0.) as u32;
+            let last = ((max_ * z2 + buffer).floor().max(0.) as u32).min(max);
+            (first, last)
+        };
+
+        let (limit_x0, limit_x1) = range(limit.min.x, limit.max.x, 0., max_x);
+        let (limit_y0, limit_y1) = range(limit.min.y, limit.max.y, 0., max_y);
+        let buffer = self.options.tile.buffer as f64 / self.options.tile.extent as f64;
+        let mut tiles = BTreeSet::new();
+        for feature in self.bounds.iter().filter(|f| f.in_zoom_range(z)) {
+            let (x0, x1) = range(feature.bbox.min.x, feature.bbox.max.x, buffer, max_x);
+            let (y0, y1) = range(feature.bbox.min.y, feature.bbox.max.y, buffer, max_y);
+            for x in x0.max(limit_x0)..=x1.min(limit_x1) {
+                for y in y0.max(limit_y0)..=y1.min(limit_y1) {
+                    tiles.insert((x, y));
+                }
+            }
+        }
+        Ok(tiles.into_iter().map(|(x, y)| (z, x, y)).collect())
     }
 
-    fn split_tile_zeros(&mut self, features: &VtFeatures, z: u8, x: u32, y: u32) {
-        self.split_tile(features, z, x, y, 0, 0, 0)
+    #[allow(dead_code)]
+    pub(crate) fn get_internal_tiles(&self) -> HashMap<u64, InternalTile> {
+        self.read_tiles().tiles.clone()
     }
 
     fn split_tile(
-        &mut self,
+        &self,
+        store: &mut TileStore,
         features: &VtFeatures,
         z: u8,
         x: u32,
         y: u32,
-        cz: u8,
-        cx: u32,
-        cy: u32,
+        split: Split,
     ) {
         let z2: f64 = (1u32 << z) as f64;
         let id = to_id(z, x, y);
 
         // try insert
-        match self.tiles.entry(id) {
-            Entry::Occupied(_) => {}
+        match store.tiles.entry(id) {
+            Entry::Occupied(_) => {
+                // tiles that survived an edit are still valid, and so are their descendants
+                if split == Split::Rebuild {
+                    return;
+                }
+            }
             Entry::Vacant(entry) => {
-                let tolerance = if z == self.options.max_zoom {
-                    0.
-                } else {
-                    self.options.tile.tolerance / (z2 * self.options.tile.extent as f64)
-                };
-
                 entry.insert(InternalTile::new(
                     features,
                     z,
                     x,
                     y,
-                    self.options.tile.extent,
-                    tolerance,
-                    self.options.tile.line_metrics,
+                    &self.options.tile,
+                    self.tile_tolerance(z),
+                    self.grid_zoom(z),
                 ));
 
-                self.stats.insert(
-                    z,
-                    if self.stats.contains_key(&z) {
-                        self.stats[&z] + 1
-                    } else {
-                        1
-                    },
-                );
-                self.total += 1;
                 //println!("tile z{z}-{x}-{y}");
             }
         };
 
-        let tile = self.tiles.get_mut(&id).expect("can no longer be None");
+        let tile = store.tiles.get_mut(&id).expect("can no longer be None");
 
         if features.is_empty() {
             //eprintln!("no feature");
             return;
         }
 
-        // if it's the first-pass tiling
-        if cz == 0u8 {
-            // stop tiling if we reached max zoom, or if the tile is too simple
-            if z == self.options.index_max_zoom
-                || tile.tile.num_points <= self.options.index_max_points
-            {
-                //println!("reached max zoom");
-                tile.source_features = features.clone();
-                return;
-            }
-        } else {
+        if let Split::DrillDown(cz, cx, cy) = split {
+            // drilled tiles of a bounded cache can be evicted, so they are drilled down again from
+            // the tile holding the source geometry instead
+            let keep_source = self.options.cache_limit.is_none();
+
             // drilldown to a specific tile;
             // stop tiling if we reached base zoom
-            if z == self.options.max_zoom {
+            if z == self.index_zoom(self.options.max_zoom) {
                 //println!("reached base zoom");
                 return;
             }
 
             // stop tiling if it's our target tile zoom
             if z == cz {
-                tile.source_features = features.clone();
+                if keep_source {
+                    tile.source_features = Arc::new(features.clone());
+                }
                 //println!("target tile zoom");
                 return;
             }
@@ -332,113 +1006,236 @@ impl GeoJSONVT {
             let b = (cy as f64 / m).floor() as u32;
             if x != a || y != b {
                 //println!("not an ancestor");
-                tile.source_features = features.clone();
+                if keep_source {
+                    tile.source_features = Arc::new(features.clone());
+                }
+                return;
+            }
+        } else {
+            // first-pass tiling; stop tiling if we reached max zoom, or if the tile is too simple
+            if z == self.index_zoom(self.options.index_max_zoom)
+                || tile.compact.num_points <= self.options.index_max_points
+            {
+                //println!("reached max zoom");
+                tile.source_features = Arc::new(features.clone());
                 return;
             }
         }
 
+        // features are not sliced any further than their max zoom
+        let child_zoom = self.grid_zoom(z + 1);
+        let visible: VtFeatures;
+        let features = if features.iter().any(|f| f.max_zoom < child_zoom) {
+            visible = features
+                .iter()
+                .filter(|f| f.max_zoom >= child_zoom)
+                .cloned()
+                .collect();
+            &visible
+        } else {
+            features
+        };
+
         let p: f64 = 0.5 * self.options.tile.buffer as f64 / self.options.tile.extent as f64; // TODO: Is the calculation order right?
         let min = tile.bbox.min;
         let max = tile.bbox.max;
 
-        let left = clip::<0>(
-            features,
-            (x as f64 - p) / z2,
-            (x as f64 + 0.5 + p) / z2,
-            min.x,
-            max.x,
-            self.options.tile.line_metrics,
-        );
+        #[cfg(feature = "rayon")]
+        if split == Split::Index {
+            self.split_quadrants_parallel(store, features, z, x, y, p, min, max);
 
-        self.split_tile(
-            &clip::<1>(
-                &left,
-                (y as f64 - p) / z2,
-                (y as f64 + 0.5 + p) / z2,
-                min.y,
-                max.y,
-                self.options.tile.line_metrics,
-            ),
-            z + 1,
-            x * 2,
-            y * 2,
-            cz,
-            cx,
-            cy,
-        );
+            let tile = store.tiles.get_mut(&id).expect("can no longer be None");
+            tile.source_features = Arc::default();
+            return;
+        }
 
-        self.split_tile(
-            &clip::<1>(
-                &left,
-                (y as f64 + 0.5 - p) / z2,
-                (y as f64 + 1. + p) / z2,
-                min.y,
-                max.y,
+        // after an edit only the dropped tiles are sliced again, the kept ones are complete
+        let [top_left, bottom_left, top_right, bottom_right] = [(0, 0), (0, 1), (1, 0), (1, 1)]
+            .map(|(dx, dy)| {
+                split != Split::Rebuild
+                    || !store
+                        .tiles
+                        .contains_key(&to_id(z + 1, x * 2 + dx, y * 2 + dy))
+            });
+
+        if top_left || bottom_left {
+            let left = clip::<0>(
+                features,
+                (x as f64 - p) / z2,
+                (x as f64 + 0.5 + p) / z2,
+                min.x,
+                max.x,
                 self.options.tile.line_metrics,
-            ),
-            z + 1,
-            x * 2,
-            y * 2 + 1,
-            cz,
-            cx,
-            cy,
-        );
+            );
 
-        let right = clip::<0>(
-            features,
-            (x as f64 + 0.5 - p) / z2,
-            (x as f64 + 1. + p) / z2,
-            min.x,
-            max.x,
-            self.options.tile.line_metrics,
-        );
+            if top_left {
+                self.split_tile(
+                    store,
+                    &clip::<1>(
+                        &left,
+                        (y as f64 - p) / z2,
+                        (y as f64 + 0.5 + p) / z2,
+                        min.y,
+                        max.y,
+                        self.options.tile.line_metrics,
+                    ),
+                    z + 1,
+                    x * 2,
+                    y * 2,
+                    split,
+                );
+            }
 
-        self.split_tile(
-            &clip::<1>(
-                &right,
-                (y as f64 - p) / z2,
-                (y as f64 + 0.5 + p) / z2,
-                min.y,
-                max.y,
-                self.options.tile.line_metrics,
-            ),
-            z + 1,
-            x * 2 + 1,
-            y * 2,
-            cz,
-            cx,
-            cy,
-        );
+            if bottom_left {
+                self.split_tile(
+                    store,
+                    &clip::<1>(
+                        &left,
+                        (y as f64 + 0.5 - p) / z2,
+                        (y as f64 + 1. + p) / z2,
+                        min.y,
+                        max.y,
+                        self.options.tile.line_metrics,
+                    ),
+                    z + 1,
+                    x * 2,
+                    y * 2 + 1,
+                    split,
+                );
+            }
+        }
 
-        self.split_tile(
-            &clip::<1>(
-                &right,
-                (y as f64 + 0.5 - p) / z2,
-                (y as f64 + 1. + p) / z2,
-                min.y,
-                max.y,
+        if top_right || bottom_right {
+            let right = clip::<0>(
+                features,
+                (x as f64 + 0.5 - p) / z2,
+                (x as f64 + 1. + p) / z2,
+                min.x,
+                max.x,
                 self.options.tile.line_metrics,
-            ),
-            z + 1,
-            x * 2 + 1,
-            y * 2 + 1,
-            cz,
-            cx,
-            cy,
-        );
+            );
+
+            if top_right {
+                self.split_tile(
+                    store,
+                    &clip::<1>(
+                        &right,
+                        (y as f64 - p) / z2,
+                        (y as f64 + 0.5 + p) / z2,
+                        min.y,
+                        max.y,
+                        self.options.tile.line_metrics,
+                    ),
+                    z + 1,
+                    x * 2 + 1,
+                    y * 2,
+                    split,
+                );
+            }
+
+            if bottom_right {
+                self.split_tile(
+                    store,
+                    &clip::<1>(
+                        &right,
+                        (y as f64 + 0.5 - p) / z2,
+                        (y as f64 + 1. + p) / z2,
+                        min.y,
+                        max.y,
+                        self.options.tile.line_metrics,
+                    ),
+                    z + 1,
+                    x * 2 + 1,
+                    y * 2 + 1,
+                    split,
+                );
+            }
+        }
+
+        // the tile a drill down of a bounded cache starts from keeps its source geometry
+        if let Split::DrillDown(..) = split {
+            if self.options.cache_limit.is_some() {
+                return;
+            }
+        }
 
         // if we sliced further down, no need to keep source geometry
         // TODO Cleanup, dont fetch twice
-        let tile = self.tiles.get_mut(&id).expect("can no longer be None");
-        tile.source_features = Vec::new();
+        let tile = store.tiles.get_mut(&id).expect("can no longer be None");
+        tile.source_features = Arc::default();
     }
 
-    pub(crate) fn stats(&self) -> &HashMap<u8, u32> {
-        &self.stats
-    }
+    // The first-pass tiling slices the whole dataset, so the subtrees of the four quadrants are
+    // sliced in parallel and merged afterwards. This gives the same tiles as the sequential path.
+    #[cfg(feature = "rayon")]
+    #[allow(clippy::too_many_arguments)]
+    fn split_quadrants_parallel(
+        &self,
+        store: &mut TileStore,
+        features: &VtFeatures,
+        z: u8,
+        x: u32,
+        y: u32,
+        p: f64,
+        min: Point2D,
+        max: Point2D,
+    ) {
+        use rayon::prelude::*;
 
-    pub(crate) fn total(&self) -> u32 {
-        self.total
+        let z2: f64 = (1u32 << z) as f64;
+        let line_metrics = self.options.tile.line_metrics;
+
+        let (left, right) = rayon::join(
+            || {
+                clip::<0>(
+                    features,
+                    (x as f64 - p) / z2,
+                    (x as f64 + 0.5 + p) / z2,
+                    min.x,
+                    max.x,
+                    line_metrics,
+                )
+            },
+            || {
+                clip::<0>(
+                    features,
+                    (x as f64 + 0.5 - p) / z2,
+                    (x as f64 + 1. + p) / z2,
+                    min.x,
+                    max.x,
+                    line_metrics,
+                )
+            },
+        );
+
+        let top = ((y as f64 - p) / z2, (y as f64 + 0.5 + p) / z2);
+        let bottom = ((y as f64 + 0.5 - p) / z2, (y as f64 + 1. + p) / z2);
+        let quadrants = [
+            (&left, x * 2, y * 2, top),
+            (&left, x * 2, y * 2 + 1, bottom),
+            (&right, x * 2 + 1, y * 2, top),
+            (&right, x * 2 + 1, y * 2 + 1, bottom),
+        ];
+
+        let subtrees: Vec<TileStore> = quadrants
+            .into_par_iter()
+            .map(|(half, x, y, (k1, k2))| {
+                let mut subtree = TileStore::default();
+                self.split_tile(
+                    &mut subtree,
+                    &clip::<1>(half, k1, k2, min.y, max.y, line_metrics),
+                    z + 1,
+                    x,
+                    y,
+                    Split::Index,
+                );
+                subtree
+            })
+            .collect();
+
+        for subtree in subtrees {
+            store.extend(subtree);
+        }
     }
 }
 
@@ -0,0 +1,161 @@
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection};
+
+use crate::error::Error;
+use crate::mvt::{encode_tile, LayerFields};
+use crate::GeoJSONVT;
+
+// Export of a tile pyramid as MBTiles 1.3
+// https://github.com/mapbox/mbtiles-spec/blob/master/1.3/spec.md
+
+#[derive(Clone)]
+pub struct MbtilesOptions {
+    pub name: String,  // name of the tileset in the metadata
+    pub layer: String, // name of the vector layer the features are encoded into
+    pub min_zoom: u8,
+    pub max_zoom: u8,   // can't be higher than the max zoom of the index
+    pub compress: bool, // gzip the encoded tiles
+}
+
+impl Default for MbtilesOptions {
+    fn default() -> Self {
+        Self {
+            name: "geojson-vt".to_string(),
+            layer: "geojsonLayer".to_string(),
+            min_zoom: 0,
+            max_zoom: 14,
+            compress: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MbtilesError {
+    Tile(Error),
+    Sqlite(rusqlite::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MbtilesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MbtilesError::Tile(error) => write!(f, "failed to generate tile: {error}"),
+            MbtilesError::Sqlite(error) => write!(f, "failed to write mbtiles: {error}"),
+            MbtilesError::Io(error) => write!(f, "failed to compress tile: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for MbtilesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MbtilesError::Tile(error) => Some(error),
+            MbtilesError::Sqlite(error) => Some(error),
+            MbtilesError::Io(error) => Some(error),
+        }
+    }
+}
+
+impl From<Error> for MbtilesError {
+    fn from(error: Error) -> Self {
+        MbtilesError::Tile(error)
+    }
+}
+
+impl From<rusqlite::Error> for MbtilesError {
+    fn from(error: rusqlite::Error) -> Self {
+        MbtilesError::Sqlite(error)
+    }
+}
+
+impl From<std::io::Error> for MbtilesError {
+    fn from(error: std::io::Error) -> Self {
+        MbtilesError::Io(error)
+    }
+}
+
+// Writes the tiles between the min and max zoom into a new MBTiles file. Only tiles containing
+// features are written, with rows in the TMS scheme. Returns the number of tiles written.
+pub fn export_mbtiles<P: AsRef<Path>>(
+    index: &GeoJSONVT,
+    path: P,
+    options: &MbtilesOptions,
+) -> Result<u64, MbtilesError> {
+    let mut connection = Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "CREATE TABLE metadata (name TEXT, value TEXT);
+         CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+         CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);",
+    )?;
+
+    let max_zoom = options.max_zoom.min(index.options.max_zoom);
+    let extent = index.options.tile.extent;
+    let mut fields = LayerFields::default();
+    let mut written = 0;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        index.for_each_tile(max_zoom, |z, x, y, tile| {
+            if z < options.min_zoom || tile.features.features.is_empty() {
+                return Ok::<(), MbtilesError>(());
+            }
+
+            fields.add(tile);
+
+            let mut data = encode_tile(tile, &options.layer, extent);
+            if options.compress {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&data)?;
+                data = encoder.finish()?;
+            }
+
+            let row = (1u32 << z) - 1 - y;
+            insert.execute(params![z, x, row, data])?;
+            written += 1;
+            Ok(())
+        })?;
+    }
+
+    let vector_layers = serde_json::json!({
+        "vector_layers": fields.vector_layers(&options.layer, options.min_zoom, max_zoom),
+    });
+
+    let mut metadata = vec![
+        ("name", options.name.clone()),
+        ("format", "pbf".to_string()),
+        ("type", "overlay".to_string()),
+        ("version", "2".to_string()),
+        ("minzoom", options.min_zoom.to_string()),
+        ("maxzoom", max_zoom.to_string()),
+        ("json", vector_layers.to_string()),
+    ];
+    // the bounds are in degrees, so they are left out for other projections
+    if let Some([west, south, east, north]) = index.bounds() {
+        metadata.push(("bounds", format!("{west},{south},{east},{north}")));
+        metadata.push((
+            "center",
+            format!(
+                "{},{},{}",
+                (west + east) / 2.,
+                (south + north) / 2.,
+                options.min_zoom
+            ),
+        ));
+    }
+    for (name, value) in metadata {
+        transaction.execute(
+            "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+            params![name, value],
+        )?;
+    }
+
+    transaction.commit()?;
+    Ok(written)
+}
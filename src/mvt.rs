@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+
+use geojson::feature::Id;
+use geojson::{Feature, JsonValue, LineStringType, PolygonType, Value};
+
+use crate::layers::LayeredTile;
+use crate::tile::Tile;
+
+// Encoding of tiles as Mapbox Vector Tiles (version 2.1)
+// https://github.com/mapbox/vector-tile-spec/tree/master/2.1
+
+// field numbers of vector_tile.proto
+const TILE_LAYERS: u32 = 3;
+
+const LAYER_NAME: u32 = 1;
+const LAYER_FEATURES: u32 = 2;
+const LAYER_KEYS: u32 = 3;
+const LAYER_VALUES: u32 = 4;
+const LAYER_EXTENT: u32 = 5;
+const LAYER_VERSION: u32 = 15;
+
+const FEATURE_ID: u32 = 1;
+const FEATURE_TAGS: u32 = 2;
+const FEATURE_TYPE: u32 = 3;
+const FEATURE_GEOMETRY: u32 = 4;
+
+const VALUE_STRING: u32 = 1;
+const VALUE_DOUBLE: u32 = 3;
+const VALUE_UINT: u32 = 5;
+const VALUE_SINT: u32 = 6;
+const VALUE_BOOL: u32 = 7;
+
+// protobuf wire types
+const WIRE_VARINT: u32 = 0;
+const WIRE_FIXED64: u32 = 1;
+const WIRE_LEN: u32 = 2;
+
+// geometry types
+const GEOM_POINT: u64 = 1;
+const GEOM_LINESTRING: u64 = 2;
+const GEOM_POLYGON: u64 = 3;
+
+// geometry commands
+const CMD_MOVE_TO: u32 = 1;
+const CMD_LINE_TO: u32 = 2;
+const CMD_CLOSE_PATH: u32 = 7;
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type) as u64);
+    }
+
+    fn uint_field(&mut self, field: u32, value: u64) {
+        self.key(field, WIRE_VARINT);
+        self.varint(value);
+    }
+
+    fn double_field(&mut self, field: u32, value: f64) {
+        self.key(field, WIRE_FIXED64);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes_field(&mut self, field: u32, value: &[u8]) {
+        self.key(field, WIRE_LEN);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn packed_field(&mut self, field: u32, values: &[u32]) {
+        let mut packed = Writer::default();
+        for value in values {
+            packed.varint(*value as u64);
+        }
+        self.bytes_field(field, &packed.buf);
+    }
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+// values of the layer value table, floats are compared by their bit pattern
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum MvtValue {
+    String(String),
+    Double(u64),
+    Uint(u64),
+    Sint(i64),
+    Bool(bool),
+}
+
+impl MvtValue {
+    fn from_json(value: &JsonValue) -> Option<Self> {
+        match value {
+            JsonValue::Null => None,
+            JsonValue::Bool(value) => Some(MvtValue::Bool(*value)),
+            JsonValue::Number(number) => {
+                if let Some(value) = number.as_u64() {
+                    Some(MvtValue::Uint(value))
+                } else if let Some(value) = number.as_i64() {
+                    Some(MvtValue::Sint(value))
+                } else {
+                    number
+                        .as_f64()
+                        .map(|value| MvtValue::Double(value.to_bits()))
+                }
+            }
+            JsonValue::String(value) => Some(MvtValue::String(value.clone())),
+            // nested values are not supported by the spec, store them serialized
+            JsonValue::Array(_) | JsonValue::Object(_) => Some(MvtValue::String(value.to_string())),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        match self {
+            MvtValue::String(value) => writer.bytes_field(VALUE_STRING, value.as_bytes()),
+            MvtValue::Double(bits) => writer.double_field(VALUE_DOUBLE, f64::from_bits(*bits)),
+            MvtValue::Uint(value) => writer.uint_field(VALUE_UINT, *value),
+            MvtValue::Sint(value) => {
+                writer.uint_field(VALUE_SINT, ((value << 1) ^ (value >> 63)) as u64)
+            }
+            MvtValue::Bool(value) => writer.uint_field(VALUE_BOOL, *value as u64),
+        }
+        writer.buf
+    }
+}
+
+#[derive(Default)]
+struct GeometryEncoder {
+    commands: Vec<u32>,
+    cursor: (i32, i32),
+}
+
+impl GeometryEncoder {
+    fn to_tile_coords(position: &[f64]) -> (i32, i32) {
+        (position[0].round() as i32, position[1].round() as i32)
+    }
+
+    fn move_to(&mut self, points: &[(i32, i32)]) {
+        self.commands
+            .push(command(CMD_MOVE_TO, points.len() as u32));
+        self.push_points(points);
+    }
+
+    fn line_to(&mut self, points: &[(i32, i32)]) {
+        self.commands
+            .push(command(CMD_LINE_TO, points.len() as u32));
+        self.push_points(points);
+    }
+
+    fn push_points(&mut self, points: &[(i32, i32)]) {
+        for &(x, y) in points {
+            self.commands.push(zigzag(x - self.cursor.0));
+            self.commands.push(zigzag(y - self.cursor.1));
+            self.cursor = (x, y);
+        }
+    }
+
+    fn add_points(&mut self, points: &[Vec<f64>]) {
+        let points: Vec<_> = points.iter().map(|p| Self::to_tile_coords(p)).collect();
+        if !points.is_empty() {
+            self.move_to(&points);
+        }
+    }
+
+    // converts a line to tile coordinates and drops repeated points
+    fn dedup_line(line: &LineStringType) -> Vec<(i32, i32)> {
+        let mut points: Vec<(i32, i32)> = line.iter().map(|p| Self::to_tile_coords(p)).collect();
+        points.dedup();
+        points
+    }
+
+    fn add_line(&mut self, line: &LineStringType) {
+        let points = Self::dedup_line(line);
+        if points.len() < 2 {
+            return;
+        }
+        self.move_to(&points[..1]);
+        self.line_to(&points[1..]);
+    }
+
+    fn add_polygon(&mut self, polygon: &PolygonType) {
+        for (i, ring) in polygon.iter().enumerate() {
+            let mut points = Self::dedup_line(ring);
+            // the closing point is implied by the ClosePath command
+            if points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            let area = if points.len() < 3 {
+                0
+            } else {
+                signed_area(&points)
+            };
+            if area == 0 {
+                if i == 0 {
+                    // holes without an exterior ring can't be represented
+                    return;
+                }
+                continue;
+            }
+
+            // exterior rings must have a positive area in tile coordinates, holes a negative one
+            if (i == 0) != (area > 0) {
+                points[1..].reverse();
+            }
+
+            self.move_to(&points[..1]);
+            self.line_to(&points[1..]);
+            self.commands.push(command(CMD_CLOSE_PATH, 1));
+        }
+    }
+}
+
+fn signed_area(points: &[(i32, i32)]) -> i64 {
+    let mut area = 0i64;
+    for i in 0..points.len() {
+        let (x1, y1) = points[i];
+        let (x2, y2) = points[(i + 1) % points.len()];
+        area += x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64;
+    }
+    area
+}
+
+fn encode_geometry(value: &Value) -> Option<(u64, Vec<u32>)> {
+    let mut encoder = GeometryEncoder::default();
+    let geom_type = match value {
+        Value::Point(point) => {
+            encoder.add_points(std::slice::from_ref(point));
+            GEOM_POINT
+        }
+        Value::MultiPoint(points) => {
+            encoder.add_points(points);
+            GEOM_POINT
+        }
+        Value::LineString(line) => {
+            encoder.add_line(line);
+            GEOM_LINESTRING
+        }
+        Value::MultiLineString(lines) => {
+            for line in lines {
+                encoder.add_line(line);
+            }
+            GEOM_LINESTRING
+        }
+        Value::Polygon(polygon) => {
+            encoder.add_polygon(polygon);
+            GEOM_POLYGON
+        }
+        Value::MultiPolygon(polygons) => {
+            for polygon in polygons {
+                encoder.add_polygon(polygon);
+            }
+            GEOM_POLYGON
+        }
+        // tiles never contain collections, they are split into single features
+        Value::GeometryCollection(_) => return None,
+    };
+
+    if encoder.commands.is_empty() {
+        None
+    } else {
+        Some((geom_type, encoder.commands))
+    }
+}
+
+#[derive(Default)]
+struct LayerBuilder {
+    features: Vec<Vec<u8>>,
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<MvtValue>,
+    value_index: HashMap<MvtValue, u32>,
+}
+
+impl LayerBuilder {
+    fn key(&mut self, key: &str) -> u32 {
+        if let Some(index) = self.key_index.get(key) {
+            return *index;
+        }
+        let index = self.keys.len() as u32;
+        self.keys.push(key.to_string());
+        self.key_index.insert(key.to_string(), index);
+        index
+    }
+
+    fn value(&mut self, value: MvtValue) -> u32 {
+        if let Some(index) = self.value_index.get(&value) {
+            return *index;
+        }
+        let index = self.values.len() as u32;
+        self.values.push(value.clone());
+        self.value_index.insert(value, index);
+        index
+    }
+
+    fn add_feature(&mut self, feature: &Feature) {
+        let Some(geometry) = &feature.geometry else {
+            return;
+        };
+        let Some((geom_type, commands)) = encode_geometry(&geometry.value) else {
+            return;
+        };
+
+        let mut tags = Vec::new();
+        if let Some(properties) = &feature.properties {
+            for (key, value) in properties {
+                if let Some(value) = MvtValue::from_json(value) {
+                    tags.push(self.key(key));
+                    tags.push(self.value(value));
+                }
+            }
+        }
+
+        let mut writer = Writer::default();
+        // only unsigned integer ids can be represented
+        if let Some(Id::Number(id)) = &feature.id {
+            if let Some(id) = id.as_u64() {
+                writer.uint_field(FEATURE_ID, id);
+            }
+        }
+        if !tags.is_empty() {
+            writer.packed_field(FEATURE_TAGS, &tags);
+        }
+        writer.uint_field(FEATURE_TYPE, geom_type);
+        writer.packed_field(FEATURE_GEOMETRY, &commands);
+        self.features.push(writer.buf);
+    }
+
+    fn finish(self, name: &str, extent: u16) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.uint_field(LAYER_VERSION, 2);
+        writer.bytes_field(LAYER_NAME, name.as_bytes());
+        for feature in &self.features {
+            writer.bytes_field(LAYER_FEATURES, feature);
+        }
+        for key in &self.keys {
+            writer.bytes_field(LAYER_KEYS, key.as_bytes());
+        }
+        for value in &self.values {
+            writer.bytes_field(LAYER_VALUES, &value.encode());
+        }
+        writer.uint_field(LAYER_EXTENT, extent as u64);
+        writer.buf
+    }
+}
+
+impl Writer {
+    fn layer(&mut self, name: &str, tile: &Tile, extent: u16) {
+        let mut layer = LayerBuilder::default();
+        for feature in &tile.features.features {
+            layer.add_feature(feature);
+        }
+        self.bytes_field(TILE_LAYERS, &layer.finish(name, extent));
+    }
+}
+
+// Encodes the features of a tile as a single named layer of a vector tile.
+// The extent has to match the extent the tile was generated with.
+pub fn encode_tile(tile: &Tile, layer_name: &str, extent: u16) -> Vec<u8> {
+    encode_layers([(layer_name, tile)], extent)
+}
+
+// Encodes several tiles of the same coordinate as layers of one vector tile.
+pub fn encode_layers<'a, I>(layers: I, extent: u16) -> Vec<u8>
+where
+    I: IntoIterator<Item = (&'a str, &'a Tile)>,
+{
+    let mut writer = Writer::default();
+    for (name, tile) in layers {
+        writer.layer(name, tile, extent);
+    }
+    writer.buf
+}
+
+// Encodes a tile of a layered index, every layer keeps the extent of its own options.
+pub fn encode_layered_tile(tile: &LayeredTile) -> Vec<u8> {
+    let mut writer = Writer::default();
+    for layer in &tile.layers {
+        writer.layer(layer.name, &layer.tile, layer.extent);
+    }
+    writer.buf
+}
+
+// Property types of a layer, as listed in the `vector_layers` of tileset metadata
+#[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+#[derive(Default)]
+pub(crate) struct LayerFields(std::collections::BTreeMap<String, &'static str>);
+
+#[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+impl LayerFields {
+    pub fn add(&mut self, tile: &Tile) {
+        for properties in tile.features.features.iter().flat_map(|f| &f.properties) {
+            for (key, value) in properties {
+                let kind = match value {
+                    JsonValue::Null => continue,
+                    JsonValue::Bool(_) => "Boolean",
+                    JsonValue::Number(_) => "Number",
+                    _ => "String",
+                };
+                let field = self.0.entry(key.clone()).or_insert(kind);
+                if *field != kind {
+                    *field = "Mixed";
+                }
+            }
+        }
+    }
+
+    pub fn vector_layers(&self, layer: &str, min_zoom: u8, max_zoom: u8) -> JsonValue {
+        serde_json::json!([{
+            "id": layer,
+            "fields": self.0,
+            "minzoom": min_zoom,
+            "maxzoom": max_zoom,
+        }])
+    }
+}
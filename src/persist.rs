@@ -0,0 +1,899 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, RwLock};
+
+use geojson::feature::Id;
+use geojson::{JsonObject, JsonValue};
+use serde_json::Number;
+
+use crate::compact::{CompactFeature, CompactGeometry, CompactTile, TilePoint};
+use crate::error::{GeometryError, InvalidFeature};
+use crate::projection::{CustomBounds, Equirectangular, WebMercator};
+use crate::tile::{InternalTile, EMPTY_TILE};
+use crate::types::*;
+use crate::{
+    stats, to_id, BBox, CacheLimit, CachedTile, FeatureBounds, FeatureZoom, Filter, GeoJSONVT,
+    Options, Point2D, PromoteId, PropertyFilter, TileOptions, TileStore, ValidationPolicy,
+};
+
+// Binary format of a saved index. Integers are varints, floats little endian. Tiles are stored in
+// their compact form, the GeoJSON views are created again on request.
+const MAGIC: &[u8; 4] = b"GJVT";
+const VERSION: u64 = 1;
+// Nesting of JSON values and geometry collections, deeper input is rejected before it overflows
+// the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug)]
+pub enum PersistError {
+    Io(std::io::Error),
+    UnsupportedVersion(u64),
+    InvalidFormat(&'static str),
+    UnsupportedProjection, // only the built-in projections can be restored
+    UnsupportedCallback(&'static str), // name of the option holding it
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(error) => write!(f, "failed to read or write index: {error}"),
+            PersistError::UnsupportedVersion(version) => {
+                write!(f, "unsupported index version {version}")
+            }
+            PersistError::InvalidFormat(reason) => write!(f, "invalid index file: {reason}"),
+            PersistError::UnsupportedProjection => {
+                write!(f, "custom projections can't be saved")
+            }
+            PersistError::UnsupportedCallback(option) => {
+                write!(f, "the {option} callback can't be saved")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PersistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PersistError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PersistError {
+    fn from(error: std::io::Error) -> Self {
+        PersistError::Io(error)
+    }
+}
+
+struct Writer<W: Write> {
+    out: W,
+    // positions of the properties and ids written so far, by address of the shared value
+    properties: HashMap<*const JsonObject, u64>,
+    ids: HashMap<*const Id, u64>,
+}
+
+impl<W: Write> Writer<W> {
+    fn varint(&mut self, mut value: u64) -> std::io::Result<()> {
+        let mut buf = [0u8; 10];
+        let mut len = 0;
+        while value >= 0x80 {
+            buf[len] = (value as u8 & 0x7f) | 0x80;
+            value >>= 7;
+            len += 1;
+        }
+        buf[len] = value as u8;
+        self.out.write_all(&buf[..=len])
+    }
+
+    fn f64(&mut self, value: f64) -> std::io::Result<()> {
+        self.out.write_all(&value.to_le_bytes())
+    }
+
+    fn bool(&mut self, value: bool) -> std::io::Result<()> {
+        self.varint(value as u64)
+    }
+
+    fn str(&mut self, value: &str) -> std::io::Result<()> {
+        self.varint(value.len() as u64)?;
+        self.out.write_all(value.as_bytes())
+    }
+
+    fn options(&mut self, options: &Options) -> Result<(), PersistError> {
+        self.varint(options.max_zoom as u64)?;
+        self.varint(options.index_max_zoom as u64)?;
+        self.varint(options.index_max_points as u64)?;
+        self.bool(options.generate_id)?;
+        self.bool(options.editable)?;
+        // callbacks can't be saved
+        match &options.promote_id {
+            None => self.varint(0)?,
+            Some(PromoteId::Property(name)) => {
+                self.varint(1)?;
+                self.str(name)?;
+            }
+            Some(PromoteId::Callback(_)) => {
+                return Err(PersistError::UnsupportedCallback("promote_id"))
+            }
+        }
+        self.varint(match options.validation {
+            ValidationPolicy::Skip => 0,
+            ValidationPolicy::Fail => 1,
+            ValidationPolicy::Report => 2,
+        })?;
+        match options.cache_limit {
+            None => self.varint(0)?,
+            Some(CacheLimit::Tiles(max)) => {
+                self.varint(1)?;
+                self.varint(max as u64)?;
+            }
+            Some(CacheLimit::Bytes(max)) => {
+                self.varint(2)?;
+                self.varint(max as u64)?;
+            }
+        }
+        match &options.feature_zoom {
+            None => self.varint(0)?,
+            Some(FeatureZoom::Properties { min_zoom, max_zoom }) => {
+                self.varint(1)?;
+                self.str(min_zoom)?;
+                self.str(max_zoom)?;
+            }
+            Some(FeatureZoom::Tippecanoe) => self.varint(2)?,
+            Some(FeatureZoom::Callback(_)) => {
+                return Err(PersistError::UnsupportedCallback("feature_zoom"))
+            }
+        }
+        match &options.filter {
+            None => self.varint(0)?,
+            Some(filter) => {
+                self.varint(1)?;
+                self.json(filter.json())?;
+            }
+        }
+        let projection: &dyn Any = options.projection.as_ref();
+        if projection.is::<WebMercator>() {
+            self.varint(0)?;
+        } else if projection.is::<Equirectangular>() {
+            self.varint(1)?;
+        } else if let Some(bounds) = projection.downcast_ref::<CustomBounds>() {
+            self.varint(2)?;
+            self.f64(bounds.min_x)?;
+            self.f64(bounds.min_y)?;
+            self.f64(bounds.max_x)?;
+            self.f64(bounds.max_y)?;
+        } else {
+            return Err(PersistError::UnsupportedProjection);
+        }
+        if options.tile.transform_properties.is_some() {
+            return Err(PersistError::UnsupportedCallback("transform_properties"));
+        }
+        if options.tile.feature_predicate.is_some() {
+            return Err(PersistError::UnsupportedCallback("feature_predicate"));
+        }
+        self.f64(options.tile.tolerance)?;
+        self.varint(options.tile.extent as u64)?;
+        self.varint(options.tile.buffer as u64)?;
+        self.bool(options.tile.line_metrics)?;
+        self.bool(options.tile.clean_polygons)?;
+        let names = match &options.tile.properties {
+            PropertyFilter::All => return Ok(self.varint(0)?),
+            PropertyFilter::Include(names) => {
+                self.varint(1)?;
+                names
+            }
+            PropertyFilter::Exclude(names) => {
+                self.varint(2)?;
+                names
+            }
+        };
+        self.varint(names.len() as u64)?;
+        for name in names {
+            self.str(name)?;
+        }
+        Ok(())
+    }
+
+    fn invalid_feature(&mut self, invalid: &InvalidFeature) -> std::io::Result<()> {
+        self.varint(invalid.feature as u64)?;
+        self.id(invalid.id.as_ref())?;
+        match invalid.error {
+            GeometryError::MissingGeometry => self.varint(0),
+            GeometryError::InvalidPosition(len) => {
+                self.varint(1)?;
+                self.varint(len as u64)
+            }
+            GeometryError::NonFiniteCoordinate => self.varint(2),
+            GeometryError::LatitudeOutOfRange(lat) => {
+                self.varint(3)?;
+                self.f64(lat)
+            }
+            GeometryError::TooFewPoints(len) => {
+                self.varint(4)?;
+                self.varint(len as u64)
+            }
+        }
+    }
+
+    fn json(&mut self, value: &JsonValue) -> std::io::Result<()> {
+        match value {
+            JsonValue::Null => self.varint(0),
+            JsonValue::Bool(value) => {
+                self.varint(1)?;
+                self.bool(*value)
+            }
+            JsonValue::Number(number) => self.number(number),
+            JsonValue::String(value) => {
+                self.varint(5)?;
+                self.str(value)
+            }
+            JsonValue::Array(values) => {
+                self.varint(6)?;
+                self.varint(values.len() as u64)?;
+                values.iter().try_for_each(|value| self.json(value))
+            }
+            JsonValue::Object(object) => {
+                self.varint(7)?;
+                self.object(object)
+            }
+        }
+    }
+
+    fn number(&mut self, number: &Number) -> std::io::Result<()> {
+        if let Some(value) = number.as_u64() {
+            self.varint(2)?;
+            self.varint(value)
+        } else if let Some(value) = number.as_i64() {
+            self.varint(3)?;
+            self.varint(((value << 1) ^ (value >> 63)) as u64)
+        } else {
+            self.varint(4)?;
+            self.f64(number.as_f64().unwrap_or_default())
+        }
+    }
+
+    fn object(&mut self, object: &JsonObject) -> std::io::Result<()> {
+        self.varint(object.len() as u64)?;
+        for (key, value) in object {
+            self.str(key)?;
+            self.json(value)?;
+        }
+        Ok(())
+    }
+
+    fn id(&mut self, id: Option<&Id>) -> std::io::Result<()> {
+        match id {
+            None => self.varint(0),
+            Some(Id::String(id)) => {
+                self.varint(1)?;
+                self.str(id)
+            }
+            Some(Id::Number(number)) => {
+                self.varint(2)?;
+                self.number(number)
+            }
+        }
+    }
+
+    fn bbox(&mut self, bbox: &BBox) -> std::io::Result<()> {
+        self.f64(bbox.min.x)?;
+        self.f64(bbox.min.y)?;
+        self.f64(bbox.max.x)?;
+        self.f64(bbox.max.y)
+    }
+
+    fn feature_bounds(&mut self, bounds: &FeatureBounds) -> std::io::Result<()> {
+        self.bbox(&bounds.bbox)?;
+        self.varint(bounds.min_zoom as u64)?;
+        self.varint(bounds.max_zoom as u64)
+    }
+
+    fn points(&mut self, points: &[VtPoint]) -> std::io::Result<()> {
+        self.varint(points.len() as u64)?;
+        for point in points {
+            self.f64(point.x)?;
+            self.f64(point.y)?;
+            self.f64(point.z)?;
+        }
+        Ok(())
+    }
+
+    fn line_string(&mut self, line: &VtLineString) -> std::io::Result<()> {
+        self.points(&line.elements)?;
+        self.f64(line.dist)?;
+        self.f64(line.seg_start)?;
+        self.f64(line.seg_end)
+    }
+
+    fn polygon(&mut self, rings: &VtPolygon) -> std::io::Result<()> {
+        self.varint(rings.len() as u64)?;
+        for ring in rings {
+            self.points(&ring.elements)?;
+            self.f64(ring.area)?;
+        }
+        Ok(())
+    }
+
+    fn geometry(&mut self, geometry: &VtGeometry) -> std::io::Result<()> {
+        match geometry {
+            VtGeometry::Empty(_) => self.varint(0),
+            VtGeometry::Point(point) => {
+                self.varint(1)?;
+                self.points(std::slice::from_ref(point))
+            }
+            VtGeometry::MultiPoint(points) => {
+                self.varint(2)?;
+                self.points(points)
+            }
+            VtGeometry::LineString(line) => {
+                self.varint(3)?;
+                self.line_string(line)
+            }
+            VtGeometry::MultiLineString(lines) => {
+                self.varint(4)?;
+                self.varint(lines.len() as u64)?;
+                lines.iter().try_for_each(|line| self.line_string(line))
+            }
+            VtGeometry::Polygon(rings) => {
+                self.varint(5)?;
+                self.polygon(rings)
+            }
+            VtGeometry::MultiPolygon(polygons) => {
+                self.varint(6)?;
+                self.varint(polygons.len() as u64)?;
+                polygons.iter().try_for_each(|rings| self.polygon(rings))
+            }
+            VtGeometry::GeometryCollection(geometries) => {
+                self.varint(7)?;
+                self.varint(geometries.len() as u64)?;
+                geometries
+                    .iter()
+                    .try_for_each(|geometry| self.geometry(geometry))
+            }
+        }
+    }
+
+    // Values shared by the clipped copies of a feature are written once, later copies refer to
+    // their position
+    fn shared_properties(&mut self, properties: &Arc<JsonObject>) -> std::io::Result<()> {
+        if let Some(&position) = self.properties.get(&Arc::as_ptr(properties)) {
+            return self.varint(position + 1);
+        }
+        let position = self.properties.len() as u64;
+        self.properties.insert(Arc::as_ptr(properties), position);
+        self.varint(0)?;
+        self.object(properties)
+    }
+
+    fn shared_id(&mut self, id: Option<&Arc<Id>>) -> std::io::Result<()> {
+        let Some(id) = id else {
+            return self.varint(0);
+        };
+        if let Some(&position) = self.ids.get(&Arc::as_ptr(id)) {
+            return self.varint(position + 2);
+        }
+        let position = self.ids.len() as u64;
+        self.ids.insert(Arc::as_ptr(id), position);
+        self.varint(1)?;
+        self.id(Some(id))
+    }
+
+    fn features(&mut self, features: &[VtFeature]) -> std::io::Result<()> {
+        self.varint(features.len() as u64)?;
+        for feature in features {
+            self.geometry(&feature.geometry)?;
+            self.shared_properties(&feature.properties)?;
+            self.shared_id(feature.id.as_ref())?;
+            self.bbox(&feature.bbox)?;
+            self.varint(feature.num_points as u64)?;
+            self.varint(feature.min_zoom as u64)?;
+            self.varint(feature.max_zoom as u64)?;
+        }
+        Ok(())
+    }
+
+    fn tile_points(&mut self, points: &[TilePoint]) -> std::io::Result<()> {
+        self.varint(points.len() as u64)?;
+        for point in points {
+            for value in point {
+                self.varint(((value << 1) ^ (value >> 31)) as u32 as u64)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tile(&mut self, tile: &CompactTile) -> std::io::Result<()> {
+        self.varint(tile.features.len() as u64)?;
+        for feature in &tile.features {
+            match &feature.geometry {
+                CompactGeometry::Points(points) => {
+                    self.varint(0)?;
+                    self.tile_points(points)?;
+                }
+                CompactGeometry::Lines(lines) => {
+                    self.varint(1)?;
+                    self.varint(lines.len() as u64)?;
+                    lines.iter().try_for_each(|line| self.tile_points(line))?;
+                }
+                CompactGeometry::Polygons(polygons) => {
+                    self.varint(2)?;
+                    self.varint(polygons.len() as u64)?;
+                    for rings in polygons {
+                        self.varint(rings.len() as u64)?;
+                        rings.iter().try_for_each(|ring| self.tile_points(ring))?;
+                    }
+                }
+            }
+            self.shared_properties(&feature.properties)?;
+            self.shared_id(feature.id.as_ref())?;
+        }
+        self.varint(tile.num_points as u64)?;
+        self.varint(tile.num_simplified as u64)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    properties: Vec<Arc<JsonObject>>,
+    ids: Vec<Arc<Id>>,
+}
+
+type ReadResult<T> = Result<T, PersistError>;
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> ReadResult<&'a [u8]> {
+        if len > self.bytes.len() {
+            return Err(PersistError::InvalidFormat("unexpected end of file"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> ReadResult<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(PersistError::InvalidFormat("varint too long"))
+    }
+
+    fn int<T: TryFrom<u64>>(&mut self) -> ReadResult<T> {
+        T::try_from(self.varint()?).map_err(|_| PersistError::InvalidFormat("integer overflow"))
+    }
+
+    // length of a sequence, bounded by the remaining bytes so corrupt files can't allocate much
+    fn len(&mut self) -> ReadResult<usize> {
+        let len = self.int::<usize>()?;
+        if len > self.bytes.len() {
+            return Err(PersistError::InvalidFormat("length exceeds file"));
+        }
+        Ok(len)
+    }
+
+    fn f64(&mut self) -> ReadResult<f64> {
+        let bytes = self.take(8)?;
+        Ok(f64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    }
+
+    fn bool(&mut self) -> ReadResult<bool> {
+        Ok(self.varint()? != 0)
+    }
+
+    fn string(&mut self) -> ReadResult<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| PersistError::InvalidFormat("invalid utf-8"))
+    }
+
+    fn vec<T>(&mut self, mut item: impl FnMut(&mut Self) -> ReadResult<T>) -> ReadResult<Vec<T>> {
+        let len = self.len()?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn options(&mut self) -> ReadResult<Options> {
+        Ok(Options {
+            max_zoom: self.int()?,
+            index_max_zoom: self.int()?,
+            index_max_points: self.int()?,
+            generate_id: self.bool()?,
+            editable: self.bool()?,
+            promote_id: match self.varint()? {
+                0 => None,
+                1 => Some(PromoteId::Property(self.string()?)),
+                _ => return Err(PersistError::InvalidFormat("unknown promote id")),
+            },
+            validation: match self.varint()? {
+                0 => ValidationPolicy::Skip,
+                1 => ValidationPolicy::Fail,
+                2 => ValidationPolicy::Report,
+                _ => return Err(PersistError::InvalidFormat("unknown validation policy")),
+            },
+            cache_limit: match self.varint()? {
+                0 => None,
+                1 => Some(CacheLimit::Tiles(self.int()?)),
+                2 => Some(CacheLimit::Bytes(self.int()?)),
+                _ => return Err(PersistError::InvalidFormat("unknown cache limit")),
+            },
+            feature_zoom: match self.varint()? {
+                0 => None,
+                1 => Some(FeatureZoom::Properties {
+                    min_zoom: self.string()?,
+                    max_zoom: self.string()?,
+                }),
+                2 => Some(FeatureZoom::Tippecanoe),
+                _ => return Err(PersistError::InvalidFormat("unknown feature zoom")),
+            },
+            filter: match self.varint()? {
+                0 => None,
+                1 => Some(
+                    Filter::from_json(&self.json(0)?)
+                        .map_err(|_| PersistError::InvalidFormat("invalid filter"))?,
+                ),
+                _ => return Err(PersistError::InvalidFormat("unknown filter")),
+            },
+            projection: match self.varint()? {
+                0 => Arc::new(WebMercator),
+                1 => Arc::new(Equirectangular),
+                2 => Arc::new(
+                    CustomBounds::new(self.f64()?, self.f64()?, self.f64()?, self.f64()?)
+                        .map_err(|_| PersistError::InvalidFormat("invalid bounds"))?,
+                ),
+                _ => return Err(PersistError::InvalidFormat("unknown projection")),
+            },
+            tile: TileOptions {
+                tolerance: self.f64()?,
+                extent: self.int()?,
+                buffer: self.int()?,
+                line_metrics: self.bool()?,
+                clean_polygons: self.bool()?,
+                properties: match self.varint()? {
+                    0 => PropertyFilter::All,
+                    1 => PropertyFilter::Include(self.vec(Self::string)?),
+                    2 => PropertyFilter::Exclude(self.vec(Self::string)?),
+                    _ => return Err(PersistError::InvalidFormat("unknown property filter")),
+                },
+                // callbacks are refused on save
+                transform_properties: None,
+                feature_predicate: None,
+            },
+        })
+    }
+
+    fn invalid_feature(&mut self) -> ReadResult<InvalidFeature> {
+        Ok(InvalidFeature {
+            feature: self.int()?,
+            id: self.id()?,
+            error: match self.varint()? {
+                0 => GeometryError::MissingGeometry,
+                1 => GeometryError::InvalidPosition(self.int()?),
+                2 => GeometryError::NonFiniteCoordinate,
+                3 => GeometryError::LatitudeOutOfRange(self.f64()?),
+                4 => GeometryError::TooFewPoints(self.int()?),
+                _ => return Err(PersistError::InvalidFormat("unknown geometry error")),
+            },
+        })
+    }
+
+    fn json(&mut self, depth: usize) -> ReadResult<JsonValue> {
+        if depth > MAX_DEPTH {
+            return Err(PersistError::InvalidFormat("nesting too deep"));
+        }
+        Ok(match self.varint()? {
+            0 => JsonValue::Null,
+            1 => JsonValue::Bool(self.bool()?),
+            tag @ 2..=4 => JsonValue::Number(self.number(tag)?),
+            5 => JsonValue::String(self.string()?),
+            6 => JsonValue::Array(self.vec(|reader| reader.json(depth + 1))?),
+            7 => JsonValue::Object(self.object(depth + 1)?),
+            _ => return Err(PersistError::InvalidFormat("unknown json value")),
+        })
+    }
+
+    fn number(&mut self, tag: u64) -> ReadResult<Number> {
+        Ok(match tag {
+            2 => Number::from(self.varint()?),
+            3 => {
+                let value = self.varint()?;
+                Number::from(((value >> 1) as i64) ^ -((value & 1) as i64))
+            }
+            _ => Number::from_f64(self.f64()?)
+                .ok_or(PersistError::InvalidFormat("non-finite number"))?,
+        })
+    }
+
+    fn object(&mut self, depth: usize) -> ReadResult<JsonObject> {
+        let len = self.len()?;
+        let mut object = JsonObject::new();
+        for _ in 0..len {
+            let key = self.string()?;
+            object.insert(key, self.json(depth)?);
+        }
+        Ok(object)
+    }
+
+    fn id(&mut self) -> ReadResult<Option<Id>> {
+        Ok(match self.varint()? {
+            0 => None,
+            1 => Some(Id::String(self.string()?)),
+            2 => {
+                let tag = self.varint()?;
+                Some(Id::Number(self.number(tag)?))
+            }
+            _ => return Err(PersistError::InvalidFormat("unknown id")),
+        })
+    }
+
+    fn bbox(&mut self) -> ReadResult<BBox> {
+        Ok(BBox::new(
+            Point2D::new(self.f64()?, self.f64()?),
+            Point2D::new(self.f64()?, self.f64()?),
+        ))
+    }
+
+    fn feature_bounds(&mut self) -> ReadResult<FeatureBounds> {
+        Ok(FeatureBounds {
+            bbox: self.bbox()?,
+            min_zoom: self.int()?,
+            max_zoom: self.int()?,
+        })
+    }
+
+    fn points(&mut self) -> ReadResult<Vec<VtPoint>> {
+        self.vec(|reader| Ok(VtPoint::new(reader.f64()?, reader.f64()?, reader.f64()?)))
+    }
+
+    fn line_string(&mut self) -> ReadResult<VtLineString> {
+        Ok(VtLineString {
+            elements: self.points()?,
+            dist: self.f64()?,
+            seg_start: self.f64()?,
+            seg_end: self.f64()?,
+        })
+    }
+
+    fn polygon(&mut self) -> ReadResult<VtPolygon> {
+        self.vec(|reader| {
+            Ok(VtLinearRing {
+                elements: reader.points()?,
+                area: reader.f64()?,
+            })
+        })
+    }
+
+    fn geometry(&mut self, depth: usize) -> ReadResult<VtGeometry> {
+        if depth > MAX_DEPTH {
+            return Err(PersistError::InvalidFormat("nesting too deep"));
+        }
+        Ok(match self.varint()? {
+            0 => VtGeometry::Empty(()),
+            1 => VtGeometry::Point(
+                self.points()?
+                    .pop()
+                    .ok_or(PersistError::InvalidFormat("point without coordinates"))?,
+            ),
+            2 => VtGeometry::MultiPoint(self.points()?),
+            3 => VtGeometry::LineString(self.line_string()?),
+            4 => VtGeometry::MultiLineString(self.vec(Self::line_string)?),
+            5 => VtGeometry::Polygon(self.polygon()?),
+            6 => VtGeometry::MultiPolygon(self.vec(Self::polygon)?),
+            7 => VtGeometry::GeometryCollection(self.vec(|reader| reader.geometry(depth + 1))?),
+            _ => return Err(PersistError::InvalidFormat("unknown geometry")),
+        })
+    }
+
+    fn shared_properties(&mut self) -> ReadResult<Arc<JsonObject>> {
+        Ok(match self.int::<usize>()? {
+            0 => {
+                let properties = Arc::new(self.object(0)?);
+                self.properties.push(properties.clone());
+                properties
+            }
+            position => {
+                self.properties
+                    .get(position - 1)
+                    .cloned()
+                    .ok_or(PersistError::InvalidFormat(
+                        "reference to unknown properties",
+                    ))?
+            }
+        })
+    }
+
+    fn shared_id(&mut self) -> ReadResult<Option<Arc<Id>>> {
+        Ok(Some(match self.int::<usize>()? {
+            0 => return Ok(None),
+            1 => {
+                let id = Arc::new(
+                    self.id()?
+                        .ok_or(PersistError::InvalidFormat("missing id"))?,
+                );
+                self.ids.push(id.clone());
+                id
+            }
+            position => self
+                .ids
+                .get(position - 2)
+                .cloned()
+                .ok_or(PersistError::InvalidFormat("reference to unknown id"))?,
+        }))
+    }
+
+    fn features(&mut self) -> ReadResult<VtFeatures> {
+        self.vec(|reader| {
+            Ok(VtFeature {
+                geometry: reader.geometry(0)?,
+                properties: reader.shared_properties()?,
+                id: reader.shared_id()?,
+                bbox: reader.bbox()?,
+                num_points: reader.int()?,
+                min_zoom: reader.int()?,
+                max_zoom: reader.int()?,
+            })
+        })
+    }
+
+    fn tile_points(&mut self) -> ReadResult<Vec<TilePoint>> {
+        self.vec(|reader| {
+            let mut point = [0; 2];
+            for value in &mut point {
+                let zigzag = reader.int::<u32>()?;
+                *value = (zigzag >> 1) as i32 ^ -((zigzag & 1) as i32);
+            }
+            Ok(point)
+        })
+    }
+
+    fn tile(&mut self) -> ReadResult<CompactTile> {
+        let features = self.vec(|reader| {
+            let geometry = match reader.varint()? {
+                0 => CompactGeometry::Points(reader.tile_points()?),
+                1 => CompactGeometry::Lines(reader.vec(Self::tile_points)?),
+                2 => CompactGeometry::Polygons(reader.vec(|reader| reader.vec(Self::tile_points))?),
+                _ => return Err(PersistError::InvalidFormat("unknown tile geometry")),
+            };
+            Ok(CompactFeature {
+                geometry,
+                properties: reader.shared_properties()?,
+                id: reader.shared_id()?,
+            })
+        })?;
+        Ok(CompactTile {
+            features,
+            num_points: self.int()?,
+            num_simplified: self.int()?,
+        })
+    }
+}
+
+impl GeoJSONVT {
+    // Writes the index including all cached tiles, so it can be loaded without slicing again
+    pub fn save<W: Write>(&self, writer: W) -> Result<(), PersistError> {
+        let mut writer = Writer {
+            out: writer,
+            properties: HashMap::new(),
+            ids: HashMap::new(),
+        };
+        writer.out.write_all(MAGIC)?;
+        writer.varint(VERSION)?;
+        writer.options(&self.options)?;
+        writer.varint(self.num_features as u64)?;
+        writer.varint(self.invalid_features.len() as u64)?;
+        for invalid in &self.invalid_features {
+            writer.invalid_feature(invalid)?;
+        }
+        writer.features(&self.source)?;
+        writer.varint(self.bounds.len() as u64)?;
+        for bounds in &self.bounds {
+            writer.feature_bounds(bounds)?;
+        }
+
+        let store = self.read_tiles();
+        writer.varint(store.tiles.len() as u64)?;
+        for (id, tile) in &store.tiles {
+            writer.varint(tile.z as u64)?;
+            writer.varint(tile.x as u64)?;
+            writer.varint(tile.y as u64)?;
+            writer.bool(store.cached.contains_key(id))?;
+            writer.bbox(&tile.bbox)?;
+            writer.features(&tile.source_features)?;
+            writer.tile(&tile.compact)?;
+            writer.varint(tile.hidden_features as u64)?;
+        }
+        writer.out.flush()?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(mut reader: R) -> Result<Self, PersistError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    // Loads an index from bytes written by `save`, e.g. a memory mapped file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PersistError> {
+        let mut reader = Reader {
+            bytes,
+            properties: Vec::new(),
+            ids: Vec::new(),
+        };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(PersistError::InvalidFormat("not an index file"));
+        }
+        let version = reader.varint()?;
+        if version != VERSION {
+            return Err(PersistError::UnsupportedVersion(version));
+        }
+
+        let options = reader.options()?;
+        if options.max_zoom > crate::MAX_ZOOM || options.tile.extent == 0 {
+            return Err(PersistError::InvalidFormat("invalid options"));
+        }
+        let mut vt = GeoJSONVT {
+            options,
+            tiles: RwLock::default(),
+            empty: Arc::new(EMPTY_TILE.clone()),
+            num_features: reader.int()?,
+            invalid_features: reader.vec(Reader::invalid_feature)?,
+            source: reader.features()?,
+            bounds: reader.vec(Reader::feature_bounds)?,
+        };
+        if !vt.source.is_empty() && vt.source.len() != vt.bounds.len() {
+            return Err(PersistError::InvalidFormat(
+                "bounds don't match the features",
+            ));
+        }
+
+        let mut store = TileStore::default();
+        let len = reader.len()?;
+        for _ in 0..len {
+            let z: u8 = reader.int()?;
+            let x: u32 = reader.int()?;
+            let y: u32 = reader.int()?;
+            if z > vt.index_zoom(vt.options.max_zoom) || x >= 1u32 << z || y >= 1u32 << z {
+                return Err(PersistError::InvalidFormat("tile out of range"));
+            }
+            let cached = reader.bool()?;
+
+            let mut tile = InternalTile::new(
+                &Vec::new(),
+                z,
+                x,
+                y,
+                &vt.options.tile,
+                vt.tile_tolerance(z),
+                vt.grid_zoom(z),
+            );
+            tile.bbox = reader.bbox()?;
+            tile.source_features = Arc::new(reader.features()?);
+            tile.compact = Arc::new(reader.tile()?);
+            tile.hidden_features = reader.int()?;
+
+            let id = to_id(z, x, y);
+            if cached {
+                let bytes = stats::compact_size(&tile.compact);
+                let last_used = AtomicU64::new(0);
+                store.cached.insert(id, CachedTile { last_used, bytes });
+                store.cached_bytes += bytes;
+            }
+            store.tiles.insert(id, tile);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(PersistError::InvalidFormat("trailing bytes"));
+        }
+
+        vt.tiles = RwLock::new(store);
+        Ok(vt)
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::error::Error;
+use crate::mvt::{encode_tile, LayerFields};
+use crate::GeoJSONVT;
+
+// Writer for PMTiles version 3 archives
+// https://github.com/protomaps/PMTiles/blob/main/spec/v3/spec.md
+
+const HEADER_SIZE: usize = 127;
+// the header and the root directory have to fit into the first 16 KiB
+const ROOT_SIZE: usize = 16384;
+
+// compression and tile type values of the header
+const COMPRESSION_NONE: u8 = 1;
+const COMPRESSION_GZIP: u8 = 2;
+const TILE_TYPE_MVT: u8 = 1;
+
+// west, south, east and north of Web Mercator tiles
+const WORLD: [f64; 4] = [-180., -85.0511287798066, 180., 85.0511287798066];
+
+#[derive(Clone)]
+pub struct PmtilesOptions {
+    pub name: String,  // name of the tileset in the metadata
+    pub layer: String, // name of the vector layer the features are encoded into
+    pub min_zoom: u8,
+    pub max_zoom: u8,   // can't be higher than the max zoom of the index
+    pub compress: bool, // gzip the encoded tiles
+}
+
+impl Default for PmtilesOptions {
+    fn default() -> Self {
+        Self {
+            name: "geojson-vt".to_string(),
+            layer: "geojsonLayer".to_string(),
+            min_zoom: 0,
+            max_zoom: 14,
+            compress: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PmtilesError {
+    Tile(Error),
+    Io(std::io::Error),
+    UnsupportedGrid, // the tile ids only number grids with a single root tile
+}
+
+impl fmt::Display for PmtilesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PmtilesError::Tile(error) => write!(f, "failed to generate tile: {error}"),
+            PmtilesError::Io(error) => write!(f, "failed to write pmtiles: {error}"),
+            PmtilesError::UnsupportedGrid => {
+                write!(f, "pmtiles can't hold grids with more than one root tile")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PmtilesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PmtilesError::Tile(error) => Some(error),
+            PmtilesError::Io(error) => Some(error),
+            PmtilesError::UnsupportedGrid => None,
+        }
+    }
+}
+
+impl From<Error> for PmtilesError {
+    fn from(error: Error) -> Self {
+        PmtilesError::Tile(error)
+    }
+}
+
+impl From<std::io::Error> for PmtilesError {
+    fn from(error: std::io::Error) -> Self {
+        PmtilesError::Io(error)
+    }
+}
+
+// Position of a tile on the Hilbert curves of all zoom levels
+pub(crate) fn tile_id(z: u8, mut x: u32, mut y: u32) -> u64 {
+    let mut id = ((1u64 << (2 * z as u64)) - 1) / 3;
+    if z == 0 {
+        return id;
+    }
+    let mut s = 1u32 << (z - 1);
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        id += s as u64 * s as u64 * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    id
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DirEntry {
+    pub tile_id: u64,
+    pub offset: u64,
+    pub length: u32,
+    pub run_length: u32, // 0 for entries pointing to a leaf directory
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn serialize_directory(entries: &[DirEntry]) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_varint(&mut buf, entries.len() as u64);
+    let mut last_id = 0;
+    for entry in entries {
+        write_varint(&mut buf, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        write_varint(&mut buf, entry.run_length as u64);
+    }
+    for entry in entries {
+        write_varint(&mut buf, entry.length as u64);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        // 0 marks data directly following the previous entry
+        match i.checked_sub(1).map(|i| entries[i]) {
+            Some(previous) if entry.offset == previous.offset + previous.length as u64 => {
+                write_varint(&mut buf, 0)
+            }
+            _ => write_varint(&mut buf, entry.offset + 1),
+        }
+    }
+    gzip(&buf)
+}
+
+// Serializes the root directory and, if it doesn't fit into the first 16 KiB, the leaf
+// directories it points to. Leaves grow until the root is small enough.
+pub(crate) fn build_directories(entries: &[DirEntry]) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+    let root = serialize_directory(entries)?;
+    if root.len() <= ROOT_SIZE - HEADER_SIZE {
+        return Ok((root, Vec::new()));
+    }
+
+    let mut leaf_size = 4096;
+    loop {
+        let mut leaves = Vec::new();
+        let mut root_entries = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = serialize_directory(chunk)?;
+            root_entries.push(DirEntry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u32,
+                run_length: 0,
+            });
+            leaves.extend(leaf);
+        }
+        let root = serialize_directory(&root_entries)?;
+        if root.len() <= ROOT_SIZE - HEADER_SIZE {
+            return Ok((root, leaves));
+        }
+        leaf_size *= 2;
+    }
+}
+
+// Writes the tiles between the min and max zoom into a PMTiles archive. Only tiles containing
+// features are written, identical tiles are stored once. Returns the number of tiles written.
+// The directories come before the tile data, which is clustered in tile id order while the tiles
+// are walked depth first, so all encoded tiles are held in memory until the archive is written:
+// the export needs about as much memory as the size of the archive.
+pub fn write_pmtiles<W: Write>(
+    index: &GeoJSONVT,
+    mut writer: W,
+    options: &PmtilesOptions,
+) -> Result<u64, PmtilesError> {
+    if index.options.projection.zoom_offset() > 0 {
+        return Err(PmtilesError::UnsupportedGrid);
+    }
+    let max_zoom = options.max_zoom.min(index.options.max_zoom);
+    let extent = index.options.tile.extent;
+
+    // identical tiles are found by the hash of their data, the data is only compared on a match
+    let mut fields = LayerFields::default();
+    let mut contents: Vec<Vec<u8>> = Vec::new();
+    let mut hashes: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut tiles = Vec::new();
+    index.for_each_tile(max_zoom, |z, x, y, tile| {
+        if z < options.min_zoom || tile.features.features.is_empty() {
+            return Ok(());
+        }
+        fields.add(tile);
+
+        let mut bytes = encode_tile(tile, &options.layer, extent);
+        if options.compress {
+            bytes = gzip(&bytes)?;
+        }
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let same = hashes.entry(hasher.finish()).or_default();
+        let content = match same.iter().find(|&&i| contents[i] == bytes) {
+            Some(&i) => i,
+            None => {
+                same.push(contents.len());
+                contents.push(bytes);
+                contents.len() - 1
+            }
+        };
+        tiles.push((tile_id(z, x, y), content));
+        Ok::<(), PmtilesError>(())
+    })?;
+    tiles.sort_unstable();
+
+    // tile data is clustered, in the order of the tile ids
+    let mut offsets: Vec<Option<u64>> = vec![None; contents.len()];
+    let mut order = Vec::with_capacity(contents.len());
+    let mut data_length = 0;
+    let mut entries: Vec<DirEntry> = Vec::new();
+    for &(id, content) in &tiles {
+        let length = contents[content].len() as u32;
+        let offset = *offsets[content].get_or_insert_with(|| {
+            order.push(content);
+            data_length += length as u64;
+            data_length - length as u64
+        });
+
+        match entries.last_mut() {
+            Some(last) if last.offset == offset && last.tile_id + last.run_length as u64 == id => {
+                last.run_length += 1;
+            }
+            _ => entries.push(DirEntry {
+                tile_id: id,
+                offset,
+                length,
+                run_length: 1,
+            }),
+        }
+    }
+
+    // the header bounds are in degrees, other projections get the whole Web Mercator world
+    let [west, south, east, north] = index.bounds().unwrap_or(WORLD);
+    let metadata = gzip(
+        serde_json::json!({
+            "name": options.name,
+            "format": "pbf",
+            "type": "overlay",
+            "vector_layers": fields.vector_layers(&options.layer, options.min_zoom, max_zoom),
+        })
+        .to_string()
+        .as_bytes(),
+    )?;
+    let (root, leaves) = build_directories(&entries)?;
+
+    let root_offset = HEADER_SIZE as u64;
+    let metadata_offset = root_offset + root.len() as u64;
+    let leaves_offset = metadata_offset + metadata.len() as u64;
+    let data_offset = leaves_offset + leaves.len() as u64;
+    let e7 = |degrees: f64| ((degrees * 1e7) as i32).to_le_bytes();
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(b"PMTiles");
+    header.push(3);
+    for value in [
+        root_offset,
+        root.len() as u64,
+        metadata_offset,
+        metadata.len() as u64,
+        leaves_offset,
+        leaves.len() as u64,
+        data_offset,
+        data_length,
+        tiles.len() as u64,
+        entries.len() as u64,
+        contents.len() as u64,
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    header.push(1); // clustered
+    header.push(COMPRESSION_GZIP);
+    header.push(if options.compress {
+        COMPRESSION_GZIP
+    } else {
+        COMPRESSION_NONE
+    });
+    header.push(TILE_TYPE_MVT);
+    header.push(options.min_zoom);
+    header.push(max_zoom);
+    for degrees in [west, south, east, north] {
+        header.extend_from_slice(&e7(degrees));
+    }
+    header.push(options.min_zoom);
+    header.extend_from_slice(&e7((west + east) / 2.));
+    header.extend_from_slice(&e7((south + north) / 2.));
+
+    writer.write_all(&header)?;
+    writer.write_all(&root)?;
+    writer.write_all(&metadata)?;
+    writer.write_all(&leaves)?;
+    for content in order {
+        writer.write_all(&contents[content])?;
+    }
+    writer.flush()?;
+    Ok(tiles.len() as u64)
+}
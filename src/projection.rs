@@ -0,0 +1,156 @@
+use std::any::Any;
+use std::f64::consts::PI;
+
+use geojson::PointType;
+
+use crate::error::{Error, GeometryError};
+
+// Maps positions of the input into the square [0, 1] x [0, 1] the tiles are cut from, with x to
+// the right and y down. Tile (z, x, y) covers 1 / 2^z of the square on each side.
+pub trait Projection: Any + Send + Sync {
+    fn project(&self, position: &[f64]) -> [f64; 2];
+
+    // Inverse of `project`
+    fn unproject(&self, x: f64, y: f64) -> PointType;
+
+    // Whether x = 0 and x = 1 are the same meridian, so features crossing it are copied to the
+    // other side of the grid
+    fn wraps(&self) -> bool;
+
+    // Zoom levels of the square above the root of the tile grid. Tile (z, x, y) of a grid with
+    // two root tiles side by side is tile (z + 1, x, y) of the square.
+    fn zoom_offset(&self) -> u8 {
+        0
+    }
+
+    // Whether `unproject` gives longitude and latitude, which the exports write as the bounds of
+    // the tiles
+    fn geographic(&self) -> bool {
+        false
+    }
+
+    // Rejects positions the projection isn't defined for. Positions without two finite
+    // coordinates are rejected before.
+    fn validate(&self, _position: &[f64]) -> Result<(), GeometryError> {
+        Ok(())
+    }
+}
+
+fn validate_latitude(position: &[f64]) -> Result<(), GeometryError> {
+    if position[1].abs() > 90. {
+        return Err(GeometryError::LatitudeOutOfRange(position[1]));
+    }
+    Ok(())
+}
+
+// Spherical Web Mercator (EPSG:3857) of longitude and latitude, the default
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WebMercator;
+
+impl Projection for WebMercator {
+    fn project(&self, position: &[f64]) -> [f64; 2] {
+        let sine = (position[1] * PI / 180.).sin();
+        let x = position[0] / 360. + 0.5;
+        let y = (0.5 - 0.25 * ((1. + sine) / (1. - sine)).ln() / PI).clamp(0.0, 1.0);
+        [x, y]
+    }
+
+    fn unproject(&self, x: f64, y: f64) -> PointType {
+        let lat = 360. / PI * ((180. - y * 360.) * PI / 180.).exp().atan() - 90.;
+        vec![(x - 0.5) * 360., lat]
+    }
+
+    fn wraps(&self) -> bool {
+        true
+    }
+
+    fn geographic(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, position: &[f64]) -> Result<(), GeometryError> {
+        validate_latitude(position)
+    }
+}
+
+// Equirectangular (plate carrée, EPSG:4326) longitude and latitude on a 2:1 grid with two root
+// tiles. The world fills the upper half of the square.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn project(&self, position: &[f64]) -> [f64; 2] {
+        [position[0] / 360. + 0.5, (90. - position[1]) / 360.]
+    }
+
+    fn unproject(&self, x: f64, y: f64) -> PointType {
+        vec![(x - 0.5) * 360., 90. - y * 360.]
+    }
+
+    fn wraps(&self) -> bool {
+        true
+    }
+
+    fn zoom_offset(&self) -> u8 {
+        1
+    }
+
+    fn geographic(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, position: &[f64]) -> Result<(), GeometryError> {
+        validate_latitude(position)
+    }
+}
+
+// Projected coordinates within bounds, e.g. EPSG:3857 metres or a national grid. The bounds become
+// the root tile, so they should be square to keep pixels square. Features are clipped to the
+// bounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CustomBounds {
+    pub(crate) min_x: f64,
+    pub(crate) min_y: f64,
+    pub(crate) max_x: f64,
+    pub(crate) max_y: f64,
+}
+
+impl CustomBounds {
+    pub fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Result<Self, Error> {
+        let bounds = [min_x, min_y, max_x, max_y];
+        if !(min_x < max_x && min_y < max_y) || bounds.iter().any(|value| !value.is_finite()) {
+            return Err(Error::InvalidBounds(bounds));
+        }
+        Ok(Self {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        })
+    }
+
+    // min x, min y, max x and max y
+    pub fn bounds(&self) -> [f64; 4] {
+        [self.min_x, self.min_y, self.max_x, self.max_y]
+    }
+}
+
+impl Projection for CustomBounds {
+    fn project(&self, position: &[f64]) -> [f64; 2] {
+        [
+            (position[0] - self.min_x) / (self.max_x - self.min_x),
+            (self.max_y - position[1]) / (self.max_y - self.min_y),
+        ]
+    }
+
+    fn unproject(&self, x: f64, y: f64) -> PointType {
+        vec![
+            self.min_x + x * (self.max_x - self.min_x),
+            self.max_y - y * (self.max_y - self.min_y),
+        ]
+    }
+
+    fn wraps(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashSet};
+use std::mem::size_of;
+use std::sync::Arc;
+
+use geojson::feature::Id;
+use geojson::{Feature, JsonObject, JsonValue, Value};
+
+use crate::compact::{CompactFeature, CompactGeometry, CompactTile};
+use crate::tile::{InternalTile, Tile};
+use crate::types::{VtFeature, VtGeometry, VtPoint};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ZoomStats {
+    pub tiles: u32,
+    pub source_tiles: u32,   // tiles keeping source geometry to drill down from
+    pub num_points: u64,     // points of the tile features before simplification
+    pub num_simplified: u64, // points of the tile features after simplification
+}
+
+// Statistics of the cached tiles. Byte counts are estimates of the heap memory held by the index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexStats {
+    pub zooms: BTreeMap<u8, ZoomStats>,
+    pub total: u32,
+    pub source_tiles: u32,
+    pub tile_bytes: usize,    // generated tiles
+    pub source_bytes: usize,  // source geometry kept for drilling down
+    pub feature_bytes: usize, // features of the whole dataset kept for edits and their bounds
+}
+
+impl IndexStats {
+    pub fn total_bytes(&self) -> usize {
+        self.tile_bytes + self.source_bytes + self.feature_bytes
+    }
+
+    // `zoom` is the zoom of the tile grid, `None` for tiles above its root which are only counted
+    // in the totals
+    pub(crate) fn add_tile(
+        &mut self,
+        tile: &InternalTile,
+        zoom: Option<u8>,
+        seen: &mut HashSet<usize>,
+    ) {
+        let mut zoom = zoom.map(|z| self.zooms.entry(z).or_default());
+        if let Some(zoom) = &mut zoom {
+            zoom.tiles += 1;
+            zoom.num_points += tile.compact.num_points as u64;
+            zoom.num_simplified += tile.compact.num_simplified as u64;
+        }
+        self.total += 1;
+        self.tile_bytes += size_of::<InternalTile>() + compact_size(&tile.compact);
+        for feature in &tile.compact.features {
+            self.tile_bytes += shared_size(&feature.properties, object_size, seen);
+        }
+        if let Some(view) = tile.geojson.get() {
+            self.tile_bytes += tile_size(view);
+        }
+
+        if !tile.source_features.is_empty() {
+            if let Some(zoom) = zoom {
+                zoom.source_tiles += 1;
+            }
+            self.source_tiles += 1;
+            self.source_bytes += features_size(&tile.source_features, seen);
+        }
+    }
+}
+
+// Properties and ids are shared by the clipped copies of a feature, they are only counted for
+// the first copy in `seen`
+pub(crate) fn features_size(features: &[VtFeature], seen: &mut HashSet<usize>) -> usize {
+    features
+        .iter()
+        .map(|feature| {
+            size_of::<VtFeature>()
+                + geometry_size(&feature.geometry)
+                + shared_size(&feature.properties, object_size, seen)
+                + feature
+                    .id
+                    .as_ref()
+                    .map_or(0, |id| shared_size(id, id_size, seen))
+        })
+        .sum()
+}
+
+fn shared_size<T>(value: &Arc<T>, size: fn(&T) -> usize, seen: &mut HashSet<usize>) -> usize {
+    if seen.insert(Arc::as_ptr(value) as *const () as usize) {
+        size_of::<T>() + size(value)
+    } else {
+        0
+    }
+}
+
+fn points_size(points: &Vec<VtPoint>) -> usize {
+    points.capacity() * size_of::<VtPoint>()
+}
+
+fn geometry_size(geometry: &VtGeometry) -> usize {
+    match geometry {
+        VtGeometry::Empty(_) | VtGeometry::Point(_) => 0,
+        VtGeometry::MultiPoint(points) => points_size(points),
+        VtGeometry::LineString(line) => points_size(&line.elements),
+        VtGeometry::MultiLineString(lines) => lines
+            .iter()
+            .map(|line| size_of_val(line) + points_size(&line.elements))
+            .sum(),
+        VtGeometry::Polygon(rings) => rings
+            .iter()
+            .map(|ring| size_of_val(ring) + points_size(&ring.elements))
+            .sum(),
+        VtGeometry::MultiPolygon(polygons) => polygons
+            .iter()
+            .flatten()
+            .map(|ring| size_of_val(ring) + points_size(&ring.elements))
+            .sum(),
+        VtGeometry::GeometryCollection(geometries) => geometries
+            .iter()
+            .map(|geometry| size_of_val(geometry) + geometry_size(geometry))
+            .sum(),
+    }
+}
+
+// Without the properties, which are shared with the source features
+pub(crate) fn compact_size(tile: &CompactTile) -> usize {
+    size_of::<CompactTile>()
+        + tile
+            .features
+            .iter()
+            .map(|feature| {
+                size_of::<CompactFeature>()
+                    + match &feature.geometry {
+                        CompactGeometry::Points(points) => size_of_val(points.as_slice()),
+                        CompactGeometry::Lines(lines) => lines
+                            .iter()
+                            .map(|line| size_of_val(line) + size_of_val(line.as_slice()))
+                            .sum(),
+                        CompactGeometry::Polygons(polygons) => polygons
+                            .iter()
+                            .map(|rings| {
+                                size_of_val(rings)
+                                    + rings
+                                        .iter()
+                                        .map(|ring| {
+                                            size_of_val(ring) + size_of_val(ring.as_slice())
+                                        })
+                                        .sum::<usize>()
+                            })
+                            .sum(),
+                    }
+            })
+            .sum::<usize>()
+}
+
+fn tile_size(tile: &Tile) -> usize {
+    size_of::<Tile>()
+        + tile
+            .features
+            .features
+            .iter()
+            .map(|feature| {
+                size_of::<Feature>()
+                    + feature
+                        .geometry
+                        .as_ref()
+                        .map_or(0, |geometry| value_size(&geometry.value))
+                    + feature.properties.as_ref().map_or(0, object_size)
+                    + feature.id.as_ref().map_or(0, id_size)
+            })
+            .sum::<usize>()
+}
+
+fn positions_size(positions: &[Vec<f64>]) -> usize {
+    positions
+        .iter()
+        .map(|position| size_of_val(position) + position.capacity() * size_of::<f64>())
+        .sum()
+}
+
+fn value_size(value: &Value) -> usize {
+    match value {
+        Value::Point(position) => position.capacity() * size_of::<f64>(),
+        Value::MultiPoint(positions) | Value::LineString(positions) => positions_size(positions),
+        Value::MultiLineString(lines) | Value::Polygon(lines) => lines
+            .iter()
+            .map(|line| size_of_val(line) + positions_size(line))
+            .sum(),
+        Value::MultiPolygon(polygons) => polygons
+            .iter()
+            .flatten()
+            .map(|ring| size_of_val(ring) + positions_size(ring))
+            .sum(),
+        Value::GeometryCollection(geometries) => geometries
+            .iter()
+            .map(|geometry| size_of_val(geometry) + value_size(&geometry.value))
+            .sum(),
+    }
+}
+
+fn id_size(id: &Id) -> usize {
+    match id {
+        Id::String(id) => id.capacity(),
+        Id::Number(_) => 0,
+    }
+}
+
+fn object_size(object: &JsonObject) -> usize {
+    object
+        .iter()
+        .map(|(key, value)| {
+            size_of::<String>() + key.capacity() + size_of::<JsonValue>() + json_size(value)
+        })
+        .sum()
+}
+
+fn json_size(value: &JsonValue) -> usize {
+    match value {
+        JsonValue::String(string) => string.capacity(),
+        JsonValue::Array(values) => values
+            .iter()
+            .map(|value| size_of::<JsonValue>() + json_size(value))
+            .sum(),
+        JsonValue::Object(object) => object_size(object),
+        _ => 0,
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+use geojson::{Feature, FeatureReader, GeoJson};
+
+use crate::convert::SourceFeature;
+use crate::error::Error;
+use crate::{GeoJSONVT, Options};
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(std::io::Error),
+    Parse {
+        feature: usize, // index of the feature in the input
+        error: Box<geojson::Error>,
+    },
+    Index(Error),
+}
+
+impl StreamError {
+    fn parse(feature: usize, error: geojson::Error) -> Self {
+        match error {
+            geojson::Error::Io(error) => StreamError::Io(error),
+            error => StreamError::Parse {
+                feature,
+                error: Box::new(error),
+            },
+        }
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(error) => write!(f, "failed to read features: {error}"),
+            StreamError::Parse { feature, error } => {
+                write!(f, "failed to parse feature {feature}: {error}")
+            }
+            StreamError::Index(error) => write!(f, "failed to index features: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Io(error) => Some(error),
+            StreamError::Parse { error, .. } => Some(error.as_ref()),
+            StreamError::Index(error) => Some(error),
+        }
+    }
+}
+
+impl From<Error> for StreamError {
+    fn from(error: Error) -> Self {
+        StreamError::Index(error)
+    }
+}
+
+impl From<std::io::Error> for StreamError {
+    fn from(error: std::io::Error) -> Self {
+        StreamError::Io(error)
+    }
+}
+
+// Text of a GeoJSON text sequence line, which may start with a record separator
+fn seq_text(line: &str) -> &str {
+    line.trim_start_matches('\x1e').trim()
+}
+
+fn seq_feature(feature: usize, text: &str) -> Result<Feature, StreamError> {
+    match text.parse::<GeoJson>() {
+        Ok(GeoJson::Feature(feature)) => Ok(feature),
+        Ok(GeoJson::Geometry(geometry)) => Ok(Feature::from(geometry)),
+        Ok(GeoJson::FeatureCollection(_)) => Err(StreamError::parse(
+            feature,
+            geojson::Error::NotAFeature("FeatureCollection".to_string()),
+        )),
+        Err(error) => Err(StreamError::parse(feature, error)),
+    }
+}
+
+impl GeoJSONVT {
+    pub fn from_features<I>(features: I, options: &Options) -> Result<Self, Error>
+    where
+        I: IntoIterator,
+        I::Item: SourceFeature,
+    {
+        Self::build(features.into_iter().map(Ok::<_, Error>), options)
+    }
+
+    // Reads the features of a GeoJSON FeatureCollection one at a time
+    pub fn from_reader<R: Read>(reader: R, options: &Options) -> Result<Self, StreamError> {
+        let features = FeatureReader::from_reader(BufReader::new(reader))
+            .features()
+            .enumerate()
+            .map(|(feature, result)| result.map_err(|error| StreamError::parse(feature, error)));
+        Self::build(features, options)
+    }
+
+    // Reads newline delimited GeoJSON (GeoJSONSeq), with one feature or geometry per line
+    pub fn from_geojson_seq<R: Read>(reader: R, options: &Options) -> Result<Self, StreamError> {
+        let features = BufReader::new(reader)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if seq_text(line).is_empty()))
+            .enumerate()
+            .map(|(feature, line)| seq_feature(feature, seq_text(&line?)));
+        Self::build(features, options)
+    }
+}
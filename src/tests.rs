@@ -18,7 +18,8 @@ use crate::simplify::simplify_wrapper;
 use crate::tile::EMPTY_TILE;
 use crate::types::*;
 use crate::{
-    geojson_to_tile, GeoJSONVT, LinearRingType, MultiLineStringType, Options, TileOptions,
+    geojson_to_tile, Error, GeoJSONVT, GeometryError, LayeredGeoJSONVT, LinearRingType,
+    MultiLineStringType, Options, TileOptions, ValidationPolicy,
 };
 
 macro_rules! points {
@@ -407,9 +408,9 @@ fn get_tile_us_states() {
         File::open("fixtures/us-states.json").unwrap(),
     ))
     .unwrap();
-    let mut index = GeoJSONVT::from_geojson(&geojson, &Options::default());
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
 
-    let features = &index.get_tile(7, 37, 48).features;
+    let features = &index.get_tile(7, 37, 48).unwrap().features;
     let expected = parse_jsontile(
         serde_json::from_reader(File::open("fixtures/us-states-z7-37-48.json").unwrap()).unwrap(),
     );
@@ -418,16 +419,16 @@ fn get_tile_us_states() {
     let square = parse_jsontile(
         serde_json::from_reader(File::open("fixtures/us-states-square.json").unwrap()).unwrap(),
     );
-    let features = &index.get_tile(9, 148, 192).features;
+    let features = &index.get_tile(9, 148, 192).unwrap().features;
     assert_eq!(&square, features); // clipped square
 
-    assert_eq!(&EMPTY_TILE == index.get_tile(11, 800, 400), true); // non-existing tile
-    assert_eq!(&EMPTY_TILE == index.get_tile(11, 800, 400), true); // non-existing tile
+    assert_eq!(EMPTY_TILE == *index.get_tile(11, 800, 400).unwrap(), true); // non-existing tile
+    assert_eq!(EMPTY_TILE == *index.get_tile(11, 800, 400).unwrap(), true); // non-existing tile
 
     // This test does not make sense in C++/Rust, since the parameters are cast to integers anyway.
     // assert_eq!(isEmpty(index.getTile(-5, 123.25, 400.25)), true); // invalid tile
 
-    assert_eq!(37, index.total);
+    assert_eq!(37, index.stats().total);
 }
 
 #[test]
@@ -436,7 +437,7 @@ fn get_tile_generate_ids() {
         File::open("fixtures/us-states.json").unwrap(),
     ))
     .unwrap();
-    let mut index = GeoJSONVT::from_geojson(
+    let index = GeoJSONVT::from_geojson(
         &geojson,
         &Options {
             max_zoom: 20,
@@ -448,9 +449,10 @@ fn get_tile_generate_ids() {
             },
             ..Options::default()
         },
-    );
+    )
+    .unwrap();
 
-    let features = &index.get_tile(7, 37, 48).features;
+    let features = &index.get_tile(7, 37, 48).unwrap().features;
     let expected = parse_jsontile(
         serde_json::from_reader(File::open("fixtures/us-states-z7-37-48-gen-ids.json").unwrap())
             .unwrap(),
@@ -472,7 +474,7 @@ fn get_tile_antimerdian_triangle() {
         File::open("fixtures/dateline-triangle.json").unwrap(),
     ))
     .unwrap();
-    let mut index = GeoJSONVT::from_geojson(&geojson, &Options::default());
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
 
     #[derive(Copy, Clone, Debug)]
     struct TileCoordinate {
@@ -489,7 +491,9 @@ fn get_tile_antimerdian_triangle() {
     ];
 
     for tile_coordinate in tile_coordinates {
-        let tile = index.get_tile(tile_coordinate.z, tile_coordinate.x, tile_coordinate.y);
+        let tile = index
+            .get_tile(tile_coordinate.z, tile_coordinate.x, tile_coordinate.y)
+            .unwrap();
         assert_eq!(tile.num_points, tile.num_simplified);
         assert_eq!(
             tile.features.features.len(),
@@ -506,7 +510,7 @@ fn get_tile_polygon_clipping_bug() {
     ))
     .unwrap();
 
-    let mut index = GeoJSONVT::from_geojson(
+    let index = GeoJSONVT::from_geojson(
         &geojson,
         &Options {
             tile: TileOptions {
@@ -515,9 +519,10 @@ fn get_tile_polygon_clipping_bug() {
             },
             ..Options::default()
         },
-    );
+    )
+    .unwrap();
 
-    let tile = index.get_tile(5, 19, 9);
+    let tile = index.get_tile(5, 19, 9).unwrap();
     assert_eq!(tile.features.features.len(), 1);
     assert_eq!(tile.num_points, 5);
 
@@ -541,7 +546,7 @@ fn get_tile_projection() {
     ))
     .unwrap();
 
-    let mut index = GeoJSONVT::from_geojson(
+    let index = GeoJSONVT::from_geojson(
         &geojson,
         &Options {
             max_zoom: 20,
@@ -552,7 +557,8 @@ fn get_tile_projection() {
             },
             ..Options::default()
         },
-    );
+    )
+    .unwrap();
 
     #[derive(Copy, Clone, Debug)]
     struct TileCoordinate {
@@ -634,7 +640,9 @@ fn get_tile_projection() {
     ];
 
     for tile_coordinate in tile_coordinates {
-        let tile = index.get_tile(tile_coordinate.z, tile_coordinate.x, tile_coordinate.y);
+        let tile = index
+            .get_tile(tile_coordinate.z, tile_coordinate.x, tile_coordinate.y)
+            .unwrap();
         assert_eq!(tile.num_points, tile.num_simplified);
         assert_eq!(tile.features.features.len(), 1);
         let geometry = &tile
@@ -687,7 +695,7 @@ fn gen_tiles(
     line_metrics: bool,
 ) -> HashMap<String, FeatureCollection> {
     let geojson = GeoJson::from_str(data).unwrap();
-    let mut index = GeoJSONVT::from_geojson(
+    let index = GeoJSONVT::from_geojson(
         &geojson,
         &Options {
             max_zoom: 14,
@@ -699,14 +707,22 @@ fn gen_tiles(
             },
             ..Options::default()
         },
-    );
+    )
+    .unwrap();
 
     let mut output = HashMap::new();
 
-    let internal_tiles = index.get_internal_tiles().clone();
+    let internal_tiles = index.get_internal_tiles();
     for (_key, tile) in internal_tiles {
         let key = format!("z{}-{}-{}", tile.z, tile.x, tile.y);
-        output.insert(key, index.get_tile(tile.z, tile.x, tile.y).features.clone());
+        output.insert(
+            key,
+            index
+                .get_tile(tile.z, tile.x, tile.y)
+                .unwrap()
+                .features
+                .clone(),
+        );
     }
 
     output
@@ -840,7 +856,8 @@ fn geojson_to_tile_simple() {
         &TileOptions::default(),
         false,
         false,
-    );
+    )
+    .unwrap();
 
     assert_eq!(tile.features.features.len(), 1);
     let props = tile
@@ -872,7 +889,8 @@ fn geojson_to_tile_clips() {
         &TileOptions::default(),
         false,
         true,
-    );
+    )
+    .unwrap();
 
     assert_eq!(tile.features.features.len(), 2);
     let props = tile
@@ -905,10 +923,10 @@ fn geojson_to_tile_metrics() {
 
     let k_epsilon = 1e-5;
 
-    let tile_left = geojson_to_tile(&geojson, 13, 2342, 3133, &options, false, false);
+    let tile_left = geojson_to_tile(&geojson, 13, 2342, 3133, &options, false, false).unwrap();
     assert_eq!(tile_left.features.features.len(), 1);
 
-    let tile_right = geojson_to_tile(&geojson, 13, 2343, 3133, &options, false, false);
+    let tile_right = geojson_to_tile(&geojson, 13, 2343, 3133, &options, false, false).unwrap();
     assert_eq!(tile_right.features.features.len(), 1);
 
     let left_props = tile_left
@@ -980,9 +998,9 @@ fn geojson_to_tile_clip_vertex_on_tile_border() {
 
     let k_epsilon = 1e-5;
 
-    let mut index = GeoJSONVT::from_geojson(&geojson, &options);
+    let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
 
-    let tile = index.get_tile(13, 2344, 3134);
+    let tile = index.get_tile(13, 2344, 3134).unwrap();
     assert!(!tile.features.features.is_empty());
 
     let expected: LineStringType = LineStringType::from(&[vec![-2048., 2747.], vec![408., 5037.]]);
@@ -1017,7 +1035,7 @@ fn test_midpoint_calculation() {
         File::open("fixtures/last_feature.json").unwrap(),
     ))
     .unwrap();
-    let mut index = GeoJSONVT::from_geojson(
+    let index = GeoJSONVT::from_geojson(
         &geojson,
         &Options {
             tile: TileOptions {
@@ -1026,8 +1044,9 @@ fn test_midpoint_calculation() {
             },
             ..Options::default()
         },
-    );
-    let features = &index.get_tile(6, 11, 23).features;
+    )
+    .unwrap();
+    let features = &index.get_tile(6, 11, 23).unwrap().features;
 
     let expected1 = parse_jsontile(
         serde_json::from_reader(File::open("fixtures/last_feature-tile-fixed.json").unwrap())
@@ -1041,3 +1060,2176 @@ fn test_midpoint_calculation() {
     );
     assert_eq!(features, &expected2);
 }
+
+#[cfg(feature = "mvt")]
+fn mvt_test_tile(
+    geometry: geojson::Value,
+    properties: Option<geojson::JsonObject>,
+) -> crate::tile::Tile {
+    crate::tile::Tile {
+        features: FeatureCollection {
+            bbox: None,
+            features: vec![Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geometry)),
+                id: Some(Id::Number(Number::from(1))),
+                properties,
+                foreign_members: None,
+            }],
+            foreign_members: None,
+        },
+        num_points: 1,
+        num_simplified: 1,
+    }
+}
+
+#[cfg(feature = "mvt")]
+#[test]
+fn mvt_encode_point() {
+    let mut properties = geojson::JsonObject::new();
+    properties.insert("a".to_string(), JsonValue::String("b".to_string()));
+    let tile = mvt_test_tile(
+        geojson::Value::Point(PointType::from(&[25., 17.])),
+        Some(properties),
+    );
+
+    let expected: Vec<u8> = vec![
+        0x1A, 0x1F, // layer
+        0x78, 0x02, // version
+        0x0A, 0x01, b'l', // name
+        0x12, 0x0D, // feature
+        0x08, 0x01, // id
+        0x12, 0x02, 0x00, 0x00, // tags
+        0x18, 0x01, // type
+        0x22, 0x03, 0x09, 0x32, 0x22, // geometry
+        0x1A, 0x01, b'a', // keys
+        0x22, 0x03, 0x0A, 0x01, b'b', // values
+        0x28, 0x80, 0x20, // extent
+    ];
+    assert_eq!(crate::mvt::encode_tile(&tile, "l", 4096), expected);
+}
+
+#[cfg(feature = "mvt")]
+#[test]
+fn mvt_encode_polygon_winding() {
+    // counter-clockwise in tile coordinates, the encoder has to rewind it
+    let tile = mvt_test_tile(
+        geojson::Value::Polygon(vec![vec![
+            PointType::from(&[3., 6.]),
+            PointType::from(&[20., 34.]),
+            PointType::from(&[8., 12.]),
+            PointType::from(&[3., 6.]),
+        ]]),
+        None,
+    );
+
+    let encoded = crate::mvt::encode_tile(&tile, "l", 4096);
+    let geometry: [u8; 11] = [0x22, 0x09, 9, 6, 12, 18, 10, 12, 24, 44, 15];
+    assert!(encoded.windows(geometry.len()).any(|w| w == geometry));
+}
+
+#[test]
+fn layered_get_tile() {
+    let states = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let dateline = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/dateline.json").unwrap(),
+    ))
+    .unwrap();
+    let (GeoJson::FeatureCollection(states), GeoJson::FeatureCollection(dateline)) =
+        (states, dateline)
+    else {
+        panic!("fixtures are feature collections");
+    };
+
+    let mut index = LayeredGeoJSONVT::new();
+    index
+        .add_layer("states", &states, &Options::default())
+        .unwrap();
+    index
+        .add_layer("dateline", &dateline, &Options::default())
+        .unwrap();
+    assert_eq!(
+        index.layer_names().collect::<Vec<_>>(),
+        vec!["states", "dateline"]
+    );
+
+    let expected = parse_jsontile(
+        serde_json::from_reader(File::open("fixtures/us-states-z7-37-48.json").unwrap()).unwrap(),
+    );
+    let tile = index.get_tile(7, 37, 48).unwrap();
+    assert_eq!(tile.layer("states").unwrap().features, expected);
+
+    let dateline_index = GeoJSONVT::new(&dateline, &Options::default()).unwrap();
+    let tile = index.get_tile(0, 0, 0).unwrap();
+    assert_eq!(tile.layers.len(), 2);
+    assert_eq!(tile.layers[1].name, "dateline");
+    assert_eq!(
+        tile.layers[1].tile,
+        dateline_index.get_tile(0, 0, 0).unwrap()
+    );
+
+    // neither layer has features in the southern atlantic
+    assert!(index.get_tile(3, 3, 5).unwrap().is_empty());
+
+    // the layers of a tile have to share the grid
+    let equirectangular = Options {
+        projection: std::sync::Arc::new(crate::Equirectangular),
+        ..Options::default()
+    };
+    assert_eq!(
+        index.add_layer("plate", &states, &equirectangular),
+        Err(Error::GridMismatch {
+            layer: "plate".to_string()
+        })
+    );
+    assert_eq!(index.layer_names().count(), 2);
+
+    // the right half of a 2:1 grid is served by its layers
+    let mut plate = LayeredGeoJSONVT::new();
+    plate
+        .add_layer("dateline", &dateline, &equirectangular)
+        .unwrap();
+    plate
+        .add_layer("states", &states, &equirectangular)
+        .unwrap();
+    let states_index = GeoJSONVT::new(&states, &equirectangular).unwrap();
+    let tile = plate.get_tile(0, 0, 0).unwrap();
+    assert_eq!(
+        tile.layer("states").unwrap(),
+        states_index.get_tile(0, 0, 0).unwrap().as_ref()
+    );
+    assert!(plate.get_tile(0, 1, 0).unwrap().layer("dateline").is_some());
+    assert!(matches!(
+        plate.get_tile(0, 1, 1),
+        Err(Error::YOutOfRange { z: 0, y: 1 })
+    ));
+}
+
+#[test]
+fn layered_assign_by_property() {
+    let states = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let GeoJson::FeatureCollection(states) = states else {
+        panic!("fixture is a feature collection");
+    };
+
+    let mut index = LayeredGeoJSONVT::new();
+    index
+        .add_layers_by(&states, &Options::default(), |feature| {
+            let name = feature.property("name")?.as_str()?;
+            Some(if name < "M" { "a-l" } else { "m-z" }.to_string())
+        })
+        .unwrap();
+
+    let tile = index.get_tile(0, 0, 0).unwrap();
+    let first_half = tile.layer("a-l").unwrap();
+    let second_half = tile.layer("m-z").unwrap();
+    assert_eq!(
+        first_half.features.features.len() + second_half.features.features.len(),
+        states.features.len()
+    );
+    assert!(first_half
+        .features
+        .features
+        .iter()
+        .all(|feature| { feature.property("name").unwrap().as_str().unwrap() < "M" }));
+}
+
+#[test]
+fn get_tile_errors() {
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
+
+    assert_eq!(
+        index.get_tile(19, 0, 0).unwrap_err(),
+        Error::ZoomOutOfRange {
+            z: 19,
+            max_zoom: 18
+        }
+    );
+    assert_eq!(
+        index.get_tile(2, 0, 4).unwrap_err(),
+        Error::YOutOfRange { z: 2, y: 4 }
+    );
+    // x wraps around the world
+    assert_eq!(
+        index.get_tile(2, 4, 1).unwrap(),
+        index.get_tile(2, 0, 1).unwrap()
+    );
+
+    assert!(matches!(
+        GeoJSONVT::from_geojson(
+            &geojson,
+            &Options {
+                max_zoom: 25,
+                ..Options::default()
+            }
+        ),
+        Err(Error::ZoomOutOfRange {
+            z: 25,
+            max_zoom: 24
+        })
+    ));
+}
+
+#[test]
+fn invalid_geometry_errors() {
+    let null_geometry = GeoJson::from_str(
+        r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[0,0]}},
+            {"type":"Feature","properties":{},"geometry":null}
+        ]}"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        GeoJSONVT::from_geojson(
+            &null_geometry,
+            &Options {
+                validation: ValidationPolicy::Fail,
+                ..Options::default()
+            }
+        ),
+        Err(Error::InvalidGeometry {
+            feature: 1,
+            error: GeometryError::MissingGeometry
+        })
+    ));
+
+    let short_position = GeoJson::Geometry(Geometry::new(geojson::Value::LineString(vec![
+        PointType::from(&[0., 0.]),
+        PointType::from(&[1.]),
+    ])));
+    assert_eq!(
+        geojson_to_tile(
+            &short_position,
+            0,
+            0,
+            0,
+            &TileOptions::default(),
+            false,
+            true
+        )
+        .unwrap_err(),
+        Error::InvalidGeometry {
+            feature: 0,
+            error: GeometryError::InvalidPosition(1)
+        }
+    );
+}
+
+#[test]
+fn validation_policy() {
+    let mut collection = FeatureCollection {
+        bbox: None,
+        features: vec![],
+        foreign_members: None,
+    };
+    let mut push = |id: u64, geometry: Option<geojson::Value>| {
+        collection.features.push(Feature {
+            bbox: None,
+            geometry: geometry.map(Geometry::new),
+            id: Some(Id::Number(Number::from(id))),
+            properties: None,
+            foreign_members: None,
+        })
+    };
+    push(0, Some(geojson::Value::Point(vec![10., 10.])));
+    push(1, None);
+    push(2, Some(geojson::Value::Point(vec![f64::NAN, 10.])));
+    push(3, Some(geojson::Value::Point(vec![10., 91.])));
+    push(
+        4,
+        Some(geojson::Value::Polygon(vec![vec![
+            vec![0., 0.],
+            vec![10., 10.],
+            vec![0., 0.],
+        ]])),
+    );
+
+    let index = GeoJSONVT::new(&collection, &Options::default()).unwrap();
+    assert!(index.invalid_features().is_empty());
+    assert_eq!(index.get_tile(0, 0, 0).unwrap().features.features.len(), 1);
+
+    let index = GeoJSONVT::new(
+        &collection,
+        &Options {
+            validation: ValidationPolicy::Report,
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    let errors = index
+        .invalid_features()
+        .iter()
+        .map(|invalid| (invalid.feature, invalid.error))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            (1, GeometryError::MissingGeometry),
+            (2, GeometryError::NonFiniteCoordinate),
+            (3, GeometryError::LatitudeOutOfRange(91.)),
+            (4, GeometryError::TooFewPoints(3)),
+        ]
+    );
+    assert_eq!(
+        index.invalid_features()[0].id,
+        Some(Id::Number(Number::from(1)))
+    );
+
+    assert!(matches!(
+        GeoJSONVT::new(
+            &collection,
+            &Options {
+                validation: ValidationPolicy::Fail,
+                ..Options::default()
+            },
+        ),
+        Err(Error::InvalidGeometry { feature: 1, .. })
+    ));
+}
+
+#[test]
+fn get_tile_concurrently() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<GeoJSONVT>();
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let options = Options {
+        index_max_zoom: 2,
+        ..Options::default()
+    };
+    let shared = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    let sequential = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+
+    let tiles = (7..11u8)
+        .flat_map(|z| {
+            let (x0, y0) = (37u32 << (z - 7) as u32, 48u32 << (z - 7) as u32);
+            (0..4).flat_map(move |dx| (0..4).map(move |dy| (z, x0 + dx, y0 + dy)))
+        })
+        .collect::<Vec<_>>();
+
+    std::thread::scope(|scope| {
+        for chunk in tiles.chunks(8) {
+            let shared = &shared;
+            scope.spawn(move || {
+                for &(z, x, y) in chunk.iter().rev() {
+                    shared.get_tile(z, x, y).unwrap();
+                }
+            });
+        }
+    });
+
+    for &(z, x, y) in &tiles {
+        assert_eq!(
+            shared.get_tile(z, x, y).unwrap(),
+            sequential.get_tile(z, x, y).unwrap(),
+            "tile z{z}-{x}-{y} differs"
+        );
+    }
+}
+
+#[test]
+fn edit_features() {
+    let collection = FeatureCollection::try_from(
+        GeoJson::from_reader(BufReader::new(
+            File::open("fixtures/us-states.json").unwrap(),
+        ))
+        .unwrap(),
+    )
+    .unwrap();
+    let options = Options {
+        index_max_zoom: 2,
+        editable: true,
+        ..Options::default()
+    };
+
+    let mut tiles = (0..5u8)
+        .flat_map(|z| (0..1u32 << z).flat_map(move |x| (0..1u32 << z).map(move |y| (z, x, y))))
+        .collect::<Vec<_>>();
+    tiles.extend((7..10u8).flat_map(|z| {
+        let (x0, y0) = (37u32 << (z - 7) as u32, 48u32 << (z - 7) as u32);
+        (0..4).flat_map(move |dx| (0..4).map(move |dy| (z, x0 + dx, y0 + dy)))
+    }));
+
+    // every edit has to give the same tiles as an index built from the edited collection
+    let assert_tiles = |index: &GeoJSONVT, expected: &FeatureCollection| {
+        let expected = GeoJSONVT::new(expected, &options).unwrap();
+        for &(z, x, y) in &tiles {
+            assert_eq!(
+                index.get_tile(z, x, y).unwrap(),
+                expected.get_tile(z, x, y).unwrap(),
+                "tile z{z}-{x}-{y} differs"
+            );
+        }
+    };
+
+    let mut edited = collection.clone();
+    let inserted = edited.features.pop().unwrap();
+    let mut index = GeoJSONVT::new(&edited, &options).unwrap();
+    assert_tiles(&index, &edited);
+
+    // Puerto Rico is far away from the drilled down tiles, so they stay cached
+    let drilled = index.stats().zooms[&9].tiles;
+    index.insert_feature(&inserted).unwrap();
+    assert_eq!(index.stats().zooms[&9].tiles, drilled);
+    edited.features.push(inserted);
+    assert_tiles(&index, &edited);
+
+    // move California onto Nevada
+    let california = Id::String("06".to_string());
+    let nevada = edited
+        .features
+        .iter()
+        .find(|feature| feature.id == Some(Id::String("32".to_string())))
+        .unwrap()
+        .clone();
+    assert!(index.update_feature(&california, &nevada).unwrap());
+    let position = edited
+        .features
+        .iter()
+        .position(|feature| feature.id.as_ref() == Some(&california))
+        .unwrap();
+    edited.features[position] = Feature {
+        id: Some(california.clone()),
+        ..nevada.clone()
+    };
+    assert_tiles(&index, &edited);
+
+    assert!(index.remove_feature(&california).unwrap());
+    assert!(!index.remove_feature(&california).unwrap());
+    assert!(!index.update_feature(&california, &nevada).unwrap());
+    edited.features.remove(position);
+    assert_tiles(&index, &edited);
+
+    // without the editable option only the bounds of the features are kept
+    let mut fixed = GeoJSONVT::new(
+        &edited,
+        &Options {
+            editable: false,
+            ..options.clone()
+        },
+    )
+    .unwrap();
+    assert!(fixed.source.is_empty());
+    assert_eq!(fixed.bounds, index.bounds);
+    assert_eq!(
+        fixed.covering_tiles(4, None).unwrap(),
+        index.covering_tiles(4, None).unwrap()
+    );
+    assert!(fixed.stats().feature_bytes < index.stats().feature_bytes);
+    assert_eq!(fixed.remove_feature(&california), Err(Error::NotEditable));
+    assert_eq!(fixed.insert_feature(&nevada), Err(Error::NotEditable));
+}
+
+#[cfg(feature = "mbtiles")]
+#[test]
+fn mbtiles_export() {
+    use crate::mbtiles::{export_mbtiles, MbtilesOptions};
+    use crate::CustomBounds;
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
+
+    let path = std::env::temp_dir().join(format!("geojson-vt-{}.mbtiles", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let options = MbtilesOptions {
+        min_zoom: 1,
+        max_zoom: 5,
+        ..MbtilesOptions::default()
+    };
+    let cached = index.get_internal_tiles().len();
+    let written = export_mbtiles(&index, &path, &options).unwrap();
+    // the drilled down tiles are not added to the tile cache
+    assert_eq!(index.get_internal_tiles().len(), cached);
+    assert!(written > cached as u64);
+
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    let mut rows = connection
+        .prepare("SELECT zoom_level, tile_column, tile_row, tile_data FROM tiles")
+        .unwrap()
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, u8>(0)?,
+                row.get::<_, u32>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, Vec<u8>>(3)?,
+            ))
+        })
+        .unwrap()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len() as u64, written);
+
+    // the same tiles as requesting every tile of the grid, with flipped rows
+    let mut expected = vec![];
+    for z in 1..=5u8 {
+        for x in 0..1u32 << z {
+            for y in 0..1u32 << z {
+                if !index
+                    .get_tile(z, x, y)
+                    .unwrap()
+                    .features
+                    .features
+                    .is_empty()
+                {
+                    expected.push((z, x, (1u32 << z) - 1 - y));
+                }
+            }
+        }
+    }
+    let mut tiles = rows
+        .iter()
+        .map(|(z, x, y, _)| (*z, *x, *y))
+        .collect::<Vec<_>>();
+    tiles.sort();
+    expected.sort();
+    assert_eq!(tiles, expected);
+
+    // gzip magic bytes
+    rows.retain(|(_, _, _, data)| !data.starts_with(&[0x1f, 0x8b]));
+    assert!(rows.is_empty());
+
+    let metadata = |name: &str| -> String {
+        connection
+            .query_row(
+                "SELECT value FROM metadata WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap()
+    };
+    assert_eq!(metadata("format"), "pbf");
+    assert_eq!(metadata("minzoom"), "1");
+    assert_eq!(metadata("maxzoom"), "5");
+    let bounds = metadata("bounds")
+        .split(',')
+        .map(|v| v.parse::<f64>().unwrap())
+        .collect::<Vec<_>>();
+    assert!(bounds[0] < -170. && bounds[1] > 17. && bounds[1] < 19. && bounds[3] > 71.);
+    let json: Value = serde_json::from_str(&metadata("json")).unwrap();
+    assert_eq!(json["vector_layers"][0]["id"], "geojsonLayer");
+    assert_eq!(json["vector_layers"][0]["fields"]["name"], "String");
+    assert_eq!(json["vector_layers"][0]["fields"]["density"], "Number");
+
+    drop(connection);
+    fs::remove_file(&path).unwrap();
+
+    // bounds which aren't in degrees are left out
+    let projection = CustomBounds::new(-180., -180., 180., 180.).unwrap();
+    let projected = GeoJSONVT::from_geojson(
+        &geojson,
+        &Options {
+            projection: std::sync::Arc::new(projection),
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    export_mbtiles(&projected, &path, &options).unwrap();
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    let count: u32 = connection
+        .query_row(
+            "SELECT COUNT(*) FROM metadata WHERE name IN ('bounds', 'center')",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(count, 0);
+    drop(connection);
+    fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "pmtiles")]
+fn pmtiles_directory(bytes: &[u8]) -> Vec<crate::pmtiles::DirEntry> {
+    let mut buf = vec![];
+    flate2::read::GzDecoder::new(bytes)
+        .read_to_end(&mut buf)
+        .unwrap();
+    let mut pos = 0;
+    let mut varint = || {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = buf[pos];
+            pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte < 0x80 {
+                return value;
+            }
+        }
+    };
+
+    let len = varint() as usize;
+    let mut entries = vec![
+        crate::pmtiles::DirEntry {
+            tile_id: 0,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        };
+        len
+    ];
+    let mut last_id = 0;
+    for entry in entries.iter_mut() {
+        last_id += varint();
+        entry.tile_id = last_id;
+    }
+    for entry in entries.iter_mut() {
+        entry.run_length = varint() as u32;
+    }
+    for entry in entries.iter_mut() {
+        entry.length = varint() as u32;
+    }
+    for i in 0..len {
+        entries[i].offset = match varint() {
+            0 => entries[i - 1].offset + entries[i - 1].length as u64,
+            offset => offset - 1,
+        };
+    }
+    entries
+}
+
+#[cfg(feature = "pmtiles")]
+#[test]
+fn pmtiles_tile_ids() {
+    use crate::pmtiles::tile_id;
+
+    assert_eq!(tile_id(0, 0, 0), 0);
+    assert_eq!(tile_id(1, 0, 0), 1);
+    assert_eq!(tile_id(1, 0, 1), 2);
+    assert_eq!(tile_id(1, 1, 1), 3);
+    assert_eq!(tile_id(1, 1, 0), 4);
+    assert_eq!(tile_id(2, 0, 0), 5);
+    assert_eq!(tile_id(3, 0, 0), 21);
+    assert_eq!(tile_id(3, 7, 0), 84); // the curve ends at the top right tile
+    assert_eq!(tile_id(20, 0, 0), 366503875925);
+}
+
+#[cfg(feature = "pmtiles")]
+#[test]
+fn pmtiles_leaf_directories() {
+    use crate::pmtiles::{build_directories, DirEntry};
+
+    let entries = (0..100000u64)
+        .map(|i| DirEntry {
+            tile_id: i * 3,
+            offset: i * 1000 + i % 7,
+            length: 100 + (i % 13) as u32,
+            run_length: 1 + (i % 2) as u32,
+        })
+        .collect::<Vec<_>>();
+    let (root, leaves) = build_directories(&entries).unwrap();
+    assert!(root.len() <= 16384 - 127);
+    assert!(!leaves.is_empty());
+
+    let mut decoded = vec![];
+    for leaf in pmtiles_directory(&root) {
+        assert_eq!(leaf.run_length, 0);
+        let start = leaf.offset as usize;
+        let leaf_entries = pmtiles_directory(&leaves[start..start + leaf.length as usize]);
+        assert_eq!(leaf_entries[0].tile_id, leaf.tile_id);
+        decoded.extend(leaf_entries);
+    }
+    assert_eq!(decoded, entries);
+}
+
+#[cfg(feature = "pmtiles")]
+#[test]
+fn pmtiles_write() {
+    use crate::mvt::encode_tile;
+    use crate::pmtiles::{tile_id, write_pmtiles, PmtilesError, PmtilesOptions};
+    use crate::{CustomBounds, Equirectangular};
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
+
+    let options = PmtilesOptions {
+        max_zoom: 6,
+        ..PmtilesOptions::default()
+    };
+    let mut archive = vec![];
+    let cached = index.get_internal_tiles().len();
+    let written = write_pmtiles(&index, &mut archive, &options).unwrap();
+    // the tiles are encoded as they are walked, without requesting them from the index
+    assert_eq!(index.get_internal_tiles().len(), cached);
+    assert!(index
+        .get_internal_tiles()
+        .values()
+        .all(|tile| tile.geojson.get().is_none()));
+
+    let u64_at = |pos: usize| u64::from_le_bytes(archive[pos..pos + 8].try_into().unwrap());
+    let i32_at = |pos: usize| i32::from_le_bytes(archive[pos..pos + 4].try_into().unwrap());
+    assert_eq!(&archive[0..7], b"PMTiles");
+    assert_eq!(archive[7], 3);
+    assert_eq!(u64_at(72), written);
+    // clustered, gzip directories and tiles, mvt, zoom range
+    assert_eq!(&archive[96..102], &[1, 2, 2, 1, 0, 6]);
+    assert!(i32_at(102) < -1_700_000_000 && i32_at(114) > 710_000_000);
+
+    let (root_offset, root_len) = (u64_at(8) as usize, u64_at(16) as usize);
+    assert_eq!(u64_at(48), 0); // no leaf directories
+    let entries = pmtiles_directory(&archive[root_offset..root_offset + root_len]);
+    assert_eq!(u64_at(80), entries.len() as u64);
+    assert!(u64_at(88) < written); // duplicate tiles are stored once
+
+    let data = &archive[u64_at(56) as usize..];
+    let mut tiles = HashMap::new();
+    for entry in &entries {
+        let mut bytes = vec![];
+        let start = entry.offset as usize;
+        flate2::read::GzDecoder::new(&data[start..start + entry.length as usize])
+            .read_to_end(&mut bytes)
+            .unwrap();
+        for id in entry.tile_id..entry.tile_id + entry.run_length as u64 {
+            tiles.insert(id, bytes.clone());
+        }
+    }
+    assert_eq!(tiles.len() as u64, written);
+
+    for z in 0..=6u8 {
+        for x in 0..1u32 << z {
+            for y in 0..1u32 << z {
+                let tile = index.get_tile(z, x, y).unwrap();
+                let expected = (!tile.features.features.is_empty())
+                    .then(|| encode_tile(&tile, "geojsonLayer", 4096));
+                assert_eq!(tiles.get(&tile_id(z, x, y)), expected.as_ref());
+            }
+        }
+    }
+
+    // the tile ids only number grids with one root tile
+    let equirectangular = GeoJSONVT::from_geojson(
+        &geojson,
+        &Options {
+            projection: std::sync::Arc::new(Equirectangular),
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    assert!(matches!(
+        write_pmtiles(&equirectangular, &mut vec![], &options),
+        Err(PmtilesError::UnsupportedGrid)
+    ));
+
+    // bounds which aren't in degrees are replaced by the whole world
+    let projection = CustomBounds::new(-180., -180., 180., 180.).unwrap();
+    let projected = GeoJSONVT::from_geojson(
+        &geojson,
+        &Options {
+            projection: std::sync::Arc::new(projection),
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    let mut archive = vec![];
+    write_pmtiles(&projected, &mut archive, &options).unwrap();
+    let i32_at = |pos: usize| i32::from_le_bytes(archive[pos..pos + 4].try_into().unwrap());
+    assert_eq!(
+        [i32_at(102), i32_at(106), i32_at(110), i32_at(114)],
+        [-1_800_000_000, -850_511_287, 1_800_000_000, 850_511_287]
+    );
+    assert_eq!([i32_at(119), i32_at(123)], [0, 0]);
+}
+
+#[test]
+fn enumerate_tiles() {
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
+
+    let generated = index.generated_tiles().collect::<Vec<_>>();
+    assert!(generated.contains(&(0, 0, 0)));
+    assert!(generated.windows(2).all(|w| w[0] < w[1]));
+    for &(z, x, y) in &generated {
+        assert!(!index
+            .get_tile(z, x, y)
+            .unwrap()
+            .features
+            .features
+            .is_empty());
+    }
+    assert!(!generated.contains(&(7, 37, 48)));
+    index.get_tile(7, 37, 48).unwrap();
+    assert!(index.generated_tiles().any(|tile| tile == (7, 37, 48)));
+
+    for z in 0..7u8 {
+        let covering = index.covering_tiles(z, None).unwrap();
+        let mut non_empty = 0;
+        for x in 0..1u32 << z {
+            for y in 0..1u32 << z {
+                if !index
+                    .get_tile(z, x, y)
+                    .unwrap()
+                    .features
+                    .features
+                    .is_empty()
+                {
+                    non_empty += 1;
+                    assert!(covering.contains(&(z, x, y)), "z{z}-{x}-{y} not covered");
+                }
+            }
+        }
+        assert!(covering.len() < non_empty * 2);
+    }
+
+    // Colorado spans 3 by 3 tiles at z7
+    let colorado = index
+        .covering_tiles(7, Some([-109.0, 37.0, -102.1, 41.0]))
+        .unwrap();
+    assert_eq!(colorado.first(), Some(&(7, 25, 47)));
+    assert_eq!(colorado.last(), Some(&(7, 27, 49)));
+    assert_eq!(colorado.len(), 9);
+
+    assert_eq!(
+        index.covering_tiles(19, None),
+        Err(Error::ZoomOutOfRange {
+            z: 19,
+            max_zoom: 18
+        })
+    );
+}
+
+#[test]
+fn index_stats() {
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let options = Options {
+        index_max_points: 1000,
+        ..Options::default()
+    };
+    let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+
+    let stats = index.stats();
+    let internal_tiles = index.get_internal_tiles();
+    assert!(stats.total > 1);
+    assert_eq!(stats.total as usize, internal_tiles.len());
+    assert_eq!(
+        stats.zooms.values().map(|zoom| zoom.tiles).sum::<u32>(),
+        stats.total
+    );
+    let root = index.get_tile(0, 0, 0).unwrap();
+    assert_eq!(stats.zooms[&0].num_points, root.num_points as u64);
+    assert_eq!(stats.zooms[&0].num_simplified, root.num_simplified as u64);
+
+    let source_tiles = internal_tiles
+        .values()
+        .filter(|tile| !tile.source_features.is_empty())
+        .count();
+    assert!(source_tiles > 0);
+    assert_eq!(stats.source_tiles as usize, source_tiles);
+    assert_eq!(
+        stats
+            .zooms
+            .values()
+            .map(|zoom| zoom.source_tiles)
+            .sum::<u32>(),
+        stats.source_tiles
+    );
+    assert!(stats.tile_bytes > 0 && stats.source_bytes > 0 && stats.feature_bytes > 0);
+    assert_eq!(
+        stats.total_bytes(),
+        stats.tile_bytes + stats.source_bytes + stats.feature_bytes
+    );
+
+    index.get_tile(7, 37, 48).unwrap();
+    let drilled = index.stats();
+    assert!(drilled.total > stats.total);
+    assert!(drilled.tile_bytes > stats.tile_bytes);
+    assert_eq!(drilled.zooms[&7].tiles, 4);
+    assert_eq!(drilled.feature_bytes, stats.feature_bytes);
+}
+
+#[test]
+fn bounded_tile_cache() {
+    use crate::{to_id, CacheLimit};
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    // every tile of the index is sliced down to z5, so a drill down generates four tiles
+    let options = Options {
+        max_zoom: 6,
+        index_max_zoom: 5,
+        index_max_points: 0,
+        ..Options::default()
+    };
+    let unbounded = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    let index = GeoJSONVT::from_geojson(
+        &geojson,
+        &Options {
+            cache_limit: Some(CacheLimit::Tiles(8)),
+            ..options.clone()
+        },
+    )
+    .unwrap();
+    let indexed = index.stats();
+    let cached = |z, x, y| index.get_internal_tiles().contains_key(&to_id(z, x, y));
+
+    index.get_tile(6, 16, 24).unwrap(); // drills down (5, 8, 12)
+    index.get_tile(6, 18, 24).unwrap(); // drills down (5, 9, 12)
+    index.get_tile(6, 17, 25).unwrap(); // cached, so it is the most recently used
+    assert_eq!(index.stats().total, indexed.total + 8);
+
+    // exceeding the limit evicts the least recently used tiles down to three quarters of it
+    index.get_tile(6, 16, 22).unwrap(); // drills down (5, 8, 11)
+    assert_eq!(index.stats().total, indexed.total + 6);
+    assert!(cached(6, 17, 25) && cached(6, 16, 22));
+    assert!(!cached(6, 16, 24) && !cached(6, 17, 24) && !cached(6, 16, 25));
+
+    // tiles with source geometry are never evicted and evicted tiles are generated again
+    assert_eq!(index.stats().source_tiles, indexed.source_tiles);
+    for z in 5..=6u8 {
+        for x in 14..24u32 {
+            for y in 20..28u32 {
+                assert_eq!(
+                    index.get_tile(z, x, y).unwrap(),
+                    unbounded.get_tile(z, x, y).unwrap(),
+                    "tile z{z}-{x}-{y} differs"
+                );
+            }
+        }
+    }
+    assert!(index.stats().total <= indexed.total + 8);
+
+    // the byte limit only counts the tiles generated on request
+    let index = GeoJSONVT::from_geojson(
+        &geojson,
+        &Options {
+            cache_limit: Some(CacheLimit::Bytes(20_000)),
+            ..options
+        },
+    )
+    .unwrap();
+    for x in 14..24u32 {
+        for y in 20..28u32 {
+            assert_eq!(
+                index.get_tile(6, x, y).unwrap(),
+                unbounded.get_tile(6, x, y).unwrap()
+            );
+        }
+    }
+    let drilled = index.stats().total - indexed.total;
+    assert!(drilled > 0 && drilled < unbounded.stats().total - indexed.total);
+    // the GeoJSON views aren't counted, so they aren't cached either
+    let stats = index.stats();
+    assert!(index
+        .get_internal_tiles()
+        .values()
+        .all(|tile| tile.geojson.get().is_none()));
+    assert!(stats.tile_bytes - indexed.tile_bytes <= 20_000);
+}
+
+#[test]
+fn persist_index() {
+    use crate::{IndexStats, PersistError};
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let options = Options {
+        index_max_zoom: 2,
+        editable: true,
+        ..Options::default()
+    };
+    let mut index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    index.get_tile(7, 37, 48).unwrap(); // drills down from z2
+
+    let mut bytes = Vec::new();
+    index.save(&mut bytes).unwrap();
+    let mut loaded = GeoJSONVT::from_bytes(&bytes).unwrap();
+    // the GeoJSON views of requested tiles are not saved
+    let (loaded_stats, stats) = (loaded.stats(), index.stats());
+    assert!(loaded_stats.tile_bytes < stats.tile_bytes);
+    assert_eq!(
+        IndexStats {
+            tile_bytes: 0,
+            ..loaded_stats
+        },
+        IndexStats {
+            tile_bytes: 0,
+            ..stats
+        }
+    );
+    assert_eq!(
+        loaded.get_internal_tiles().len(),
+        index.get_internal_tiles().len()
+    );
+
+    let tiles = (0..4u8)
+        .flat_map(|z| (0..1u32 << z).flat_map(move |x| (0..1u32 << z).map(move |y| (z, x, y))))
+        .chain((7..10u8).flat_map(|z| {
+            let (x0, y0) = (37u32 << (z - 7) as u32, 48u32 << (z - 7) as u32);
+            (0..4).flat_map(move |dx| (0..4).map(move |dy| (z, x0 + dx, y0 + dy)))
+        }))
+        .collect::<Vec<_>>();
+    let assert_tiles = |a: &GeoJSONVT, b: &GeoJSONVT| {
+        for &(z, x, y) in &tiles {
+            assert_eq!(
+                a.get_tile(z, x, y).unwrap(),
+                b.get_tile(z, x, y).unwrap(),
+                "tile z{z}-{x}-{y} differs"
+            );
+        }
+    };
+    assert_tiles(&loaded, &index);
+
+    // the source features are kept, so a loaded index can still be edited
+    let california = Id::String("06".to_string());
+    assert!(index.remove_feature(&california).unwrap());
+    assert!(loaded.remove_feature(&california).unwrap());
+    assert_tiles(&loaded, &index);
+
+    // loading from a reader gives the index as it was saved
+    let reloaded = GeoJSONVT::load(bytes.as_slice()).unwrap();
+    let fresh = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    assert_tiles(&reloaded, &fresh);
+
+    assert!(matches!(
+        GeoJSONVT::from_bytes(b"GJVX"),
+        Err(PersistError::InvalidFormat(_))
+    ));
+    assert!(matches!(
+        GeoJSONVT::from_bytes(b"GJVT\x02"),
+        Err(PersistError::UnsupportedVersion(2))
+    ));
+    assert!(matches!(
+        GeoJSONVT::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(PersistError::InvalidFormat(_))
+    ));
+
+    // deeply nested values are rejected instead of overflowing the stack
+    let marker = GeoJson::from_str(
+        r#"{"type": "Feature", "properties": {"deep": "MARKER"},
+            "geometry": {"type": "Point", "coordinates": [10, 20]}}"#,
+    )
+    .unwrap();
+    let mut bytes = Vec::new();
+    GeoJSONVT::from_geojson(&marker, &Options::default())
+        .unwrap()
+        .save(&mut bytes)
+        .unwrap();
+    let encoded = b"\x05\x06MARKER";
+    let at = bytes
+        .windows(encoded.len())
+        .position(|window| window == encoded)
+        .unwrap();
+    let nested = [6u8, 1].repeat(100_000);
+    bytes.splice(at..at + encoded.len(), nested.into_iter().chain([0]));
+    assert!(matches!(
+        GeoJSONVT::from_bytes(&bytes),
+        Err(PersistError::InvalidFormat(_))
+    ));
+}
+
+#[test]
+fn stream_features() {
+    use crate::StreamError;
+
+    let collection = FeatureCollection::try_from(
+        GeoJson::from_reader(BufReader::new(
+            File::open("fixtures/us-states.json").unwrap(),
+        ))
+        .unwrap(),
+    )
+    .unwrap();
+    let options = Options {
+        index_max_zoom: 2,
+        ..Options::default()
+    };
+    let expected = GeoJSONVT::new(&collection, &options).unwrap();
+    let assert_tiles = |index: &GeoJSONVT| {
+        for z in 0..4u8 {
+            for x in 0..1u32 << z {
+                for y in 0..1u32 << z {
+                    assert_eq!(
+                        index.get_tile(z, x, y).unwrap(),
+                        expected.get_tile(z, x, y).unwrap(),
+                        "tile z{z}-{x}-{y} differs"
+                    );
+                }
+            }
+        }
+    };
+
+    let index = GeoJSONVT::from_features(&collection.features, &options).unwrap();
+    assert_tiles(&index);
+    let index = GeoJSONVT::from_features(collection.features.clone(), &options).unwrap();
+    assert_tiles(&index);
+
+    let index =
+        GeoJSONVT::from_reader(File::open("fixtures/us-states.json").unwrap(), &options).unwrap();
+    assert_tiles(&index);
+
+    // record separators and blank lines are allowed between the features
+    let mut seq = String::new();
+    for feature in &collection.features {
+        seq.push_str(&format!("\x1e{feature}\n\n"));
+    }
+    let index = GeoJSONVT::from_geojson_seq(seq.as_bytes(), &options).unwrap();
+    assert_tiles(&index);
+
+    let seq = "{\"type\":\"Point\",\"coordinates\":[0,0]}\n{\"type\":\"Feature\"";
+    assert!(matches!(
+        GeoJSONVT::from_geojson_seq(seq.as_bytes(), &options),
+        Err(StreamError::Parse { feature: 1, .. })
+    ));
+    let seq = "{\"type\":\"Point\",\"coordinates\":[0,100]}";
+    let strict = Options {
+        validation: ValidationPolicy::Fail,
+        ..options
+    };
+    assert!(matches!(
+        GeoJSONVT::from_geojson_seq(seq.as_bytes(), &strict),
+        Err(StreamError::Index(Error::InvalidGeometry {
+            feature: 0,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn from_owned_geojson() {
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let GeoJson::FeatureCollection(collection) = &geojson else {
+        panic!("expected a feature collection");
+    };
+    let feature = GeoJson::Feature(collection.features[0].clone());
+    let geometry = GeoJson::Geometry(collection.features[0].geometry.clone().unwrap());
+
+    let options = Options::default();
+    for geojson in [geojson.clone(), feature, geometry] {
+        let borrowed = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+        let owned = GeoJSONVT::from_owned(geojson, &options).unwrap();
+        for (z, x, y) in [(0, 0, 0), (1, 0, 0), (2, 0, 1), (7, 37, 48)] {
+            assert_eq!(
+                owned.get_tile(z, x, y).unwrap(),
+                borrowed.get_tile(z, x, y).unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn shared_feature_properties() {
+    use std::sync::Arc;
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let options = Options {
+        index_max_zoom: 6,
+        index_max_points: 0,
+        editable: true,
+        ..Options::default()
+    };
+
+    // every clipped copy of Texas refers to the properties and id of its source feature
+    let texas = Id::String("48".to_string());
+    let assert_shared = |index: &GeoJSONVT| {
+        let source = index
+            .source
+            .iter()
+            .find(|feature| feature.id.as_deref() == Some(&texas))
+            .unwrap();
+        let copies = index
+            .get_internal_tiles()
+            .values()
+            .flat_map(|tile| tile.source_features.iter())
+            .filter(|feature| feature.id.as_deref() == Some(&texas))
+            .inspect(|feature| {
+                assert!(Arc::ptr_eq(&feature.properties, &source.properties));
+                assert!(Arc::ptr_eq(
+                    feature.id.as_ref().unwrap(),
+                    source.id.as_ref().unwrap()
+                ));
+            })
+            .count();
+        assert!(copies > 1);
+    };
+
+    let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    assert_shared(&index);
+
+    let mut bytes = Vec::new();
+    index.save(&mut bytes).unwrap();
+    assert_shared(&GeoJSONVT::from_bytes(&bytes).unwrap());
+}
+
+#[test]
+fn compact_tiles() {
+    use crate::CompactGeometry;
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let index = GeoJSONVT::from_geojson(&geojson, &Options::default()).unwrap();
+
+    // the compact tile isn't converted to GeoJSON until `get_tile` asks for it
+    let stats = index.stats();
+    let root = index.get_tile_compact(0, 0, 0).unwrap();
+    assert_eq!(index.stats(), stats);
+    assert!(root
+        .features
+        .iter()
+        .all(|feature| matches!(feature.geometry, CompactGeometry::Polygons(_))));
+    assert_eq!(*index.get_tile(0, 0, 0).unwrap(), root.to_geojson());
+    assert!(index.stats().tile_bytes > stats.tile_bytes);
+
+    for (z, x, y) in [(1, 0, 0), (4, 3, 6), (7, 37, 48), (7, 36, 49)] {
+        let compact = index.get_tile_compact(z, x, y).unwrap();
+        assert!(!compact.features.is_empty());
+        assert_eq!(*index.get_tile(z, x, y).unwrap(), compact.to_geojson());
+    }
+
+    let empty = index.get_tile_compact(4, 0, 0).unwrap();
+    assert!(empty.features.is_empty());
+    assert!(index
+        .get_tile(4, 0, 0)
+        .unwrap()
+        .features
+        .features
+        .is_empty());
+    assert!(index.get_tile_compact(25, 0, 0).is_err());
+}
+
+#[test]
+fn clean_polygons() {
+    use crate::{CompactGeometry, TilePoint};
+
+    fn area(ring: &[TilePoint]) -> i64 {
+        ring.windows(2)
+            .map(|w| w[0][0] as i64 * w[1][1] as i64 - w[1][0] as i64 * w[0][1] as i64)
+            .sum()
+    }
+
+    // a tiny hole and a hole wound like an outer ring, then a multipolygon whose first polygon
+    // collapses at z0 and leaves its hole behind
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": null, "geometry": {"type": "Polygon", "coordinates": [
+            [[-90, -45], [90, -45], [90, 45], [-90, 45], [-90, -45]],
+            [[0, 0], [0.001, 0], [0.001, 0.001], [0, 0]],
+            [[-10, -10], [10, -10], [10, 10], [-10, 10], [-10, -10]]
+        ]}},
+        {"type": "Feature", "properties": null, "geometry": {"type": "MultiPolygon", "coordinates": [
+            [[[100, 0], [100.001, 0], [100.001, 0.001], [100, 0]],
+             [[95, -5], [105, -5], [105, 5], [95, 5], [95, -5]]],
+            [[[120, 10], [130, 10], [130, 20], [120, 20], [120, 10]]]
+        ]}}
+    ]}"#
+    .parse::<GeoJson>()
+    .unwrap();
+
+    let index = |clean_polygons| {
+        let options = Options {
+            tile: TileOptions {
+                tolerance: 0.,
+                clean_polygons,
+                ..TileOptions::default()
+            },
+            ..Options::default()
+        };
+        GeoJSONVT::from_geojson(&geojson, &options).unwrap()
+    };
+    let polygons = |index: &GeoJSONVT| -> Vec<Vec<Vec<Vec<TilePoint>>>> {
+        index
+            .get_tile_compact(0, 0, 0)
+            .unwrap()
+            .features
+            .iter()
+            .map(|feature| match &feature.geometry {
+                CompactGeometry::Polygons(polygons) => polygons.clone(),
+                geometry => panic!("unexpected geometry {geometry:?}"),
+            })
+            .collect()
+    };
+
+    let raw = polygons(&index(false));
+    assert_eq!(raw[0].len(), 1);
+    assert_eq!(raw[0][0].len(), 3);
+    assert_eq!(raw[1].len(), 2);
+
+    let clean = polygons(&index(true));
+    assert_eq!(clean.len(), 2);
+    assert_eq!(clean[0].len(), 1);
+    assert_eq!(clean[0][0].len(), 2);
+    // the surviving polygon of the multipolygon has no hole left
+    assert_eq!(clean[1].len(), 1);
+    assert_eq!(clean[1][0].len(), 1);
+
+    for rings in clean.iter().flatten() {
+        for (i, ring) in rings.iter().enumerate() {
+            assert!(ring.len() >= 4);
+            assert_eq!(ring.first(), ring.last());
+            assert_eq!(area(ring) > 0, i == 0);
+            assert_ne!(area(ring), 0);
+        }
+    }
+
+    let tile = index(true).get_tile(0, 0, 0).unwrap();
+    assert!(matches!(
+        tile.features.features[1].geometry.as_ref().unwrap().value,
+        geojson::Value::Polygon(_)
+    ));
+}
+
+#[test]
+fn cluster_points() {
+    use crate::{Aggregate, ClusterOptions, ClusterProperty, Supercluster};
+
+    // pseudo-random points around the US
+    let mut seed = 42u64;
+    let mut random = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let features = (0..2000)
+        .map(|i| Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(geojson::Value::Point(vec![
+                -125. + 58. * random(),
+                25. + 24. * random(),
+            ]))),
+            id: Some(Id::Number(Number::from(i))),
+            properties: Some(
+                serde_json::json!({"value": i % 10})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+            foreign_members: None,
+        })
+        .collect();
+    let options = ClusterOptions {
+        properties: vec![
+            ClusterProperty {
+                name: "sum".to_string(),
+                property: "value".to_string(),
+                aggregate: Aggregate::Sum,
+            },
+            ClusterProperty {
+                name: "max".to_string(),
+                property: "value".to_string(),
+                aggregate: Aggregate::Max,
+            },
+        ],
+        ..ClusterOptions::default()
+    };
+    let index = Supercluster::new(
+        &FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        },
+        &options,
+    )
+    .unwrap();
+
+    let count = |feature: &Feature| match feature.property("point_count") {
+        Some(count) => count.as_u64().unwrap(),
+        None => 1,
+    };
+    let value = |feature: &Feature, name: &str| {
+        feature
+            .property(name)
+            .or(feature.property("value"))
+            .unwrap()
+            .as_u64()
+            .unwrap()
+    };
+
+    let root = index.get_tile(0, 0, 0).unwrap();
+    let features = &root.features.features;
+    assert!(features.len() < 100);
+    assert_eq!(features.iter().map(count).sum::<u64>(), 2000);
+    assert_eq!(
+        features.iter().map(|f| value(f, "sum")).sum::<u64>(),
+        (0..2000).map(|i| i % 10).sum::<u64>()
+    );
+    assert!(features.iter().all(|f| value(f, "max") <= 9));
+
+    let cluster = features
+        .iter()
+        .filter(|f| f.property("cluster").is_some())
+        .max_by_key(|f| count(f))
+        .unwrap();
+    let cluster_id = cluster.property("cluster_id").unwrap().as_u64().unwrap();
+    assert_eq!(cluster.id, Some(Id::Number(Number::from(cluster_id))));
+
+    let children = index.get_cluster_children(cluster_id).unwrap();
+    assert!(children.len() > 1);
+    assert_eq!(children.iter().map(count).sum::<u64>(), count(cluster));
+
+    let leaves = index.get_cluster_leaves(cluster_id, usize::MAX, 0).unwrap();
+    assert_eq!(leaves.len() as u64, count(cluster));
+    assert_eq!(
+        leaves.iter().map(|f| value(f, "value")).sum::<u64>(),
+        value(cluster, "sum")
+    );
+    let page = index.get_cluster_leaves(cluster_id, 10, 5).unwrap();
+    assert_eq!(page, leaves[5..15]);
+
+    let zoom = index.get_cluster_expansion_zoom(cluster_id).unwrap();
+    assert!(zoom >= 1 && zoom <= options.max_zoom + 1);
+
+    // above max_zoom the points are never clustered
+    let leaf = &leaves[0];
+    let (x, y) = match &leaf.geometry.as_ref().unwrap().value {
+        geojson::Value::Point(p) => (p[0], p[1]),
+        _ => unreachable!(),
+    };
+    let z2 = (1u32 << 20) as f64;
+    let tile_x = ((x / 360. + 0.5) * z2) as u32;
+    let sine = (y * PI / 180.).sin();
+    let tile_y = ((0.5 - 0.25 * ((1. + sine) / (1. - sine)).ln() / PI) * z2) as u32;
+    let tile = index.get_tile(20, tile_x, tile_y).unwrap();
+    assert!(tile.features.features.iter().any(|f| f.id == leaf.id));
+    assert!(tile
+        .features
+        .features
+        .iter()
+        .all(|f| f.property("cluster").is_none()));
+
+    assert_eq!(
+        index.get_cluster_children(0),
+        Err(Error::ClusterNotFound { cluster_id: 0 })
+    );
+    assert!(index.get_cluster_expansion_zoom(1 << 40).is_err());
+    assert_eq!(
+        index.get_cluster_expansion_zoom(u64::MAX),
+        Err(Error::ClusterNotFound {
+            cluster_id: u64::MAX
+        })
+    );
+}
+
+#[test]
+fn feature_zoom_range() {
+    use crate::{FeatureZoom, PersistError};
+    use std::sync::Arc;
+
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "id": 1, "properties": {"min": 3, "max": 5},
+         "geometry": {"type": "Point", "coordinates": [10, 10]}},
+        {"type": "Feature", "id": 2, "properties": {}, "tippecanoe": {"minzoom": 2},
+         "geometry": {"type": "Point", "coordinates": [10, 10]}},
+        {"type": "Feature", "id": 3, "properties": {"max": 1},
+         "geometry": {"type": "Point", "coordinates": [10, 10]}}
+    ]}"#
+    .parse::<GeoJson>()
+    .unwrap();
+
+    let tile_of = |z: u8| {
+        let z2 = (1u32 << z) as f64;
+        let sine = (10. * PI / 180.).sin();
+        let y = 0.5 - 0.25 * ((1. + sine) / (1. - sine)).ln() / PI;
+        (((10. / 360. + 0.5) * z2) as u32, (y * z2) as u32)
+    };
+    let ids = |index: &GeoJSONVT, z: u8| {
+        let (x, y) = tile_of(z);
+        let tile = index.get_tile(z, x, y).unwrap();
+        let mut ids: Vec<u64> = tile
+            .features
+            .features
+            .iter()
+            .map(|f| match &f.id {
+                Some(Id::Number(id)) => id.as_u64().unwrap(),
+                _ => unreachable!(),
+            })
+            .collect();
+        ids.sort_unstable();
+        ids
+    };
+    let index = |feature_zoom| {
+        let options = Options {
+            max_zoom: 8,
+            index_max_zoom: 2,
+            index_max_points: 0,
+            feature_zoom: Some(feature_zoom),
+            ..Options::default()
+        };
+        GeoJSONVT::from_geojson(&geojson, &options).unwrap()
+    };
+
+    let properties = index(FeatureZoom::Properties {
+        min_zoom: "min".to_string(),
+        max_zoom: "max".to_string(),
+    });
+    assert_eq!(ids(&properties, 0), [2, 3]);
+    assert_eq!(ids(&properties, 2), [2]);
+    assert_eq!(ids(&properties, 4), [1, 2]);
+    assert_eq!(ids(&properties, 6), [2]);
+    assert!(properties
+        .covering_tiles(4, None)
+        .unwrap()
+        .contains(&(4, tile_of(4).0, tile_of(4).1)));
+
+    let tippecanoe = index(FeatureZoom::Tippecanoe);
+    assert_eq!(ids(&tippecanoe, 1), [1, 3]);
+    assert_eq!(ids(&tippecanoe, 7), [1, 2, 3]);
+
+    // only the second feature from zoom 5 on, so the tiles above are empty but are walked
+    let callback = index(FeatureZoom::Callback(Arc::new(|properties| {
+        if properties.is_empty() {
+            (5, u8::MAX)
+        } else {
+            (u8::MAX, u8::MAX)
+        }
+    })));
+    assert!(ids(&callback, 4).is_empty());
+    assert_eq!(ids(&callback, 5), [2]);
+    assert!(callback.covering_tiles(4, None).unwrap().is_empty());
+    assert!(matches!(
+        callback.save(&mut Vec::new()),
+        Err(PersistError::UnsupportedCallback("feature_zoom"))
+    ));
+    #[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+    {
+        let mut zooms = Vec::new();
+        callback
+            .for_each_tile::<Error, _>(8, |z, _, _, _| {
+                zooms.push(z);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(zooms, [5, 6, 7, 8]);
+    }
+
+    let mut bytes = Vec::new();
+    properties.save(&mut bytes).unwrap();
+    let loaded = GeoJSONVT::from_bytes(&bytes).unwrap();
+    for z in 0..=8 {
+        assert_eq!(ids(&loaded, z), ids(&properties, z));
+    }
+}
+
+#[test]
+fn promote_id() {
+    use crate::{PersistError, PromoteId};
+    use std::sync::Arc;
+
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "id": "a", "properties": {"osm_id": "123", "ref": "A1"},
+         "geometry": {"type": "Point", "coordinates": [10, 10]}},
+        {"type": "Feature", "properties": {"osm_id": 456, "ref": "B2"},
+         "geometry": {"type": "Point", "coordinates": [11, 10]}},
+        {"type": "Feature", "properties": {"osm_id": "way/7", "ref": "-3"},
+         "geometry": {"type": "Point", "coordinates": [12, 10]}},
+        {"type": "Feature", "id": 9, "properties": {"osm_id": [1]},
+         "geometry": {"type": "Point", "coordinates": [13, 10]}},
+        {"type": "Feature", "properties": {"osm_id": "007", "ref": "-0"},
+         "geometry": {"type": "Point", "coordinates": [13, 11]}},
+        {"type": "Feature", "properties": {"osm_id": "+7", "ref": "12"},
+         "geometry": {"type": "Point", "coordinates": [13, 12]}}
+    ]}"#
+    .parse::<GeoJson>()
+    .unwrap();
+
+    let ids = |promote_id| {
+        let options = Options {
+            generate_id: true,
+            editable: true,
+            promote_id: Some(promote_id),
+            ..Options::default()
+        };
+        let mut index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+        let feature = Feature {
+            properties: Some(
+                serde_json::json!({"osm_id": "77", "ref": "Z"})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+            ..geojson::Feature::from(Geometry::new(geojson::Value::Point(vec![14., 10.])))
+        };
+        index.insert_feature(&feature).unwrap();
+        index
+            .get_tile(0, 0, 0)
+            .unwrap()
+            .features
+            .features
+            .iter()
+            .map(|f| f.id.clone())
+            .collect::<Vec<_>>()
+    };
+    let number = |n: i64| Some(Id::Number(Number::from(n)));
+
+    assert_eq!(
+        ids(PromoteId::Property("osm_id".to_string())),
+        [
+            number(123),
+            number(456),
+            Some(Id::String("way/7".to_string())),
+            None,
+            Some(Id::String("007".to_string())),
+            Some(Id::String("+7".to_string())),
+            number(77)
+        ]
+    );
+    assert_eq!(
+        ids(PromoteId::Callback(Arc::new(|properties| {
+            let id = properties.get("ref")?.as_str()?;
+            Some(Id::String(id.to_string()))
+        }))),
+        [
+            Some(Id::String("A1".to_string())),
+            Some(Id::String("B2".to_string())),
+            number(-3),
+            None,
+            Some(Id::String("-0".to_string())),
+            number(12),
+            Some(Id::String("Z".to_string()))
+        ]
+    );
+
+    let options = Options {
+        promote_id: Some(PromoteId::Callback(Arc::new(|_| None))),
+        ..Options::default()
+    };
+    let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    assert!(matches!(
+        index.save(&mut Vec::new()),
+        Err(PersistError::UnsupportedCallback("promote_id"))
+    ));
+}
+
+#[test]
+fn tile_properties() {
+    use crate::{PersistError, PropertyFilter};
+    use std::sync::Arc;
+
+    let geojson =
+        r#"{"type": "Feature", "properties": {"name": "Main St", "highway": "residential",
+        "surface": "asphalt", "width": 7.25},
+        "geometry": {"type": "Point", "coordinates": [10, 10]}}"#
+            .parse::<GeoJson>()
+            .unwrap();
+    let properties = |tile: &TileOptions, z: u8| {
+        let options = Options {
+            tile: tile.clone(),
+            ..Options::default()
+        };
+        let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+        let z2 = (1u32 << z) as f64;
+        let sine = (10. * PI / 180.).sin();
+        let y = 0.5 - 0.25 * ((1. + sine) / (1. - sine)).ln() / PI;
+        let tile = index
+            .get_tile(z, ((10. / 360. + 0.5) * z2) as u32, (y * z2) as u32)
+            .unwrap();
+        tile.features.features[0].properties.clone()
+    };
+    let json = |value: Value| Some(value.as_object().unwrap().clone());
+
+    let include = TileOptions {
+        properties: PropertyFilter::Include(vec!["name".to_string(), "width".to_string()]),
+        // no names and rounded widths at low zoom
+        transform_properties: Some(Arc::new(|z, properties| {
+            if z < 10 {
+                properties.remove("name");
+                let width = properties["width"].as_f64().unwrap();
+                properties.insert("width".to_string(), Value::from(width.round() as i64));
+            }
+        })),
+        ..TileOptions::default()
+    };
+    assert_eq!(
+        properties(&include, 0),
+        json(serde_json::json!({"width": 7}))
+    );
+    assert_eq!(
+        properties(&include, 12),
+        json(serde_json::json!({"name": "Main St", "width": 7.25}))
+    );
+
+    let exclude = TileOptions {
+        properties: PropertyFilter::Exclude(vec!["surface".to_string(), "width".to_string()]),
+        ..TileOptions::default()
+    };
+    assert_eq!(
+        properties(&exclude, 3),
+        json(serde_json::json!({"name": "Main St", "highway": "residential"}))
+    );
+
+    // the filtered properties are saved, the transform isn't
+    let index = |tile: &TileOptions| {
+        let options = Options {
+            tile: tile.clone(),
+            ..Options::default()
+        };
+        GeoJSONVT::from_geojson(&geojson, &options).unwrap()
+    };
+    assert!(matches!(
+        index(&include).save(&mut Vec::new()),
+        Err(PersistError::UnsupportedCallback("transform_properties"))
+    ));
+    let mut bytes = Vec::new();
+    index(&exclude).save(&mut bytes).unwrap();
+    let loaded = GeoJSONVT::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        loaded.get_tile(0, 0, 0).unwrap().features.features[0].properties,
+        json(serde_json::json!({"name": "Main St", "highway": "residential"}))
+    );
+    assert_eq!(
+        properties(&TileOptions::default(), 3),
+        json(
+            serde_json::json!({"name": "Main St", "highway": "residential",
+            "surface": "asphalt", "width": 7.25})
+        )
+    );
+
+    // and so does a single tile
+    let single = |tile: &TileOptions| {
+        geojson_to_tile(&geojson, 0, 0, 0, tile, false, true)
+            .unwrap()
+            .features
+            .features[0]
+            .properties
+            .clone()
+    };
+    assert_eq!(single(&include), json(serde_json::json!({"width": 7})));
+    assert_eq!(
+        single(&exclude),
+        json(serde_json::json!({"name": "Main St", "highway": "residential"}))
+    );
+}
+
+#[test]
+fn filter_features() {
+    use crate::Filter;
+
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "id": 1, "properties": {"class": "primary", "lanes": 4},
+         "geometry": {"type": "LineString", "coordinates": [[-10, 10], [10, 10]]}},
+        {"type": "Feature", "id": 2, "properties": {"class": "secondary", "lanes": 2.0},
+         "geometry": {"type": "MultiLineString", "coordinates": [[[-10, 20], [10, 20]]]}},
+        {"type": "Feature", "id": 3, "properties": {"class": "track"},
+         "geometry": {"type": "LineString", "coordinates": [[-10, 30], [10, 30]]}},
+        {"type": "Feature", "id": 4, "properties": {"class": "primary", "name": "Square"},
+         "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [5, 0], [5, 5], [0, 5], [0, 0]]]}},
+        {"type": "Feature", "id": "poi", "properties": {"lanes": "4"},
+         "geometry": {"type": "Point", "coordinates": [1, 1]}}
+    ]}"#
+    .parse::<GeoJson>()
+    .unwrap();
+
+    let ids = |filter: &str| {
+        let options = Options {
+            filter: Some(filter.parse::<Filter>().unwrap()),
+            ..Options::default()
+        };
+        let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+        let tile = index.get_tile(0, 0, 0).unwrap();
+        tile.features
+            .features
+            .iter()
+            .map(|f| match f.id.as_ref().unwrap() {
+                Id::Number(id) => id.to_string(),
+                Id::String(id) => id.clone(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(ids(r#"["==", "$type", "LineString"]"#), ["1", "2", "3"]);
+    assert_eq!(ids(r#"["==", "lanes", 2]"#), ["2"]);
+    assert_eq!(ids(r#"["!=", "lanes", 4]"#), ["2", "3", "4", "poi"]);
+    assert_eq!(ids(r#"[">=", "lanes", 3]"#), ["1"]);
+    assert_eq!(ids(r#"["<", "class", "t"]"#), ["1", "2", "4"]);
+    assert_eq!(
+        ids(r#"["in", "class", "primary", "track"]"#),
+        ["1", "3", "4"]
+    );
+    assert_eq!(ids(r#"["!in", "class", "primary", "track"]"#), ["2", "poi"]);
+    assert_eq!(ids(r#"["has", "name"]"#), ["4"]);
+    assert_eq!(ids(r#"["!has", "lanes"]"#), ["3", "4"]);
+    assert_eq!(ids(r#"["in", "$id", "poi", 3]"#), ["3", "poi"]);
+    assert_eq!(
+        ids(r#"["all", ["==", "class", "primary"], ["!=", "$type", "Polygon"]]"#),
+        ["1"]
+    );
+    assert_eq!(
+        ids(r#"["any", ["==", "$type", "Point"], ["<", "lanes", 3]]"#),
+        ["2", "poi"]
+    );
+    assert_eq!(
+        ids(r#"["none", ["==", "$type", "Point"], ["has", "lanes"]]"#),
+        ["3", "4"]
+    );
+
+    // the filter is saved with the index and applies to edits
+    let options = Options {
+        filter: Some(r#"["==", "$type", "Point"]"#.parse().unwrap()),
+        editable: true,
+        ..Options::default()
+    };
+    let mut bytes = Vec::new();
+    GeoJSONVT::from_geojson(&geojson, &options)
+        .unwrap()
+        .save(&mut bytes)
+        .unwrap();
+    let mut loaded = GeoJSONVT::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.options.filter, options.filter);
+    let line = Feature::from(Geometry::new(geojson::Value::LineString(vec![
+        vec![0., 0.],
+        vec![1., 1.],
+    ])));
+    loaded.insert_feature(&line).unwrap();
+    assert_eq!(loaded.get_tile(0, 0, 0).unwrap().features.features.len(), 1);
+
+    // features are filtered before they are validated, so the ones left out can't fail the build
+    let invalid = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"class": "track"},
+         "geometry": {"type": "Point", "coordinates": [0, 100]}},
+        {"type": "Feature", "properties": {"class": "track"}, "geometry": null},
+        {"type": "Feature", "properties": {"class": "primary"},
+         "geometry": {"type": "Point", "coordinates": [0, 0]}}
+    ]}"#
+    .parse::<GeoJson>()
+    .unwrap();
+    let options = Options {
+        filter: Some(r#"["==", "class", "primary"]"#.parse().unwrap()),
+        validation: ValidationPolicy::Fail,
+        ..Options::default()
+    };
+    let index = GeoJSONVT::from_geojson(&invalid, &options).unwrap();
+    assert_eq!(index.get_tile(0, 0, 0).unwrap().features.features.len(), 1);
+
+    assert!(matches!(
+        "[\"like\", \"class\", \"p\"]".parse::<Filter>(),
+        Err(crate::FilterError::UnknownOperator(_))
+    ));
+    assert!(matches!(
+        "[\"==\", \"class\"]".parse::<Filter>(),
+        Err(crate::FilterError::InvalidFilter(_))
+    ));
+    assert!(matches!(
+        "[\"all\", 1]".parse::<Filter>(),
+        Err(crate::FilterError::InvalidFilter(_))
+    ));
+    assert!(matches!(
+        "[\"==\"".parse::<Filter>(),
+        Err(crate::FilterError::Json(_))
+    ));
+}
+
+#[test]
+fn feature_predicate() {
+    use crate::{GeometryType, PersistError, PropertyFilter};
+    use std::sync::Arc;
+
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "id": 1, "properties": {"highway": "residential", "name": "Elm St"},
+         "geometry": {"type": "LineString", "coordinates": [[10, 10], [10.01, 10.01]]}},
+        {"type": "Feature", "id": 2, "properties": {"highway": "primary", "name": "Main St"},
+         "geometry": {"type": "LineString", "coordinates": [[10, 10], [10.01, 10.02]]}},
+        {"type": "Feature", "id": 3, "properties": {"highway": "residential", "name": "Cafe"},
+         "geometry": {"type": "Point", "coordinates": [10.005, 10.005]}}
+    ]}"#
+    .parse::<GeoJson>()
+    .unwrap();
+
+    // minor streets only from zoom 12 on
+    let options = Options {
+        max_zoom: 14,
+        tile: TileOptions {
+            feature_predicate: Some(Arc::new(|z, properties, geometry_type| {
+                z >= 12
+                    || geometry_type != GeometryType::LineString
+                    || properties["highway"] != "residential"
+            })),
+            ..TileOptions::default()
+        },
+        ..Options::default()
+    };
+    let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    let tile = |index: &GeoJSONVT, z: u8| {
+        let z2 = (1u32 << z) as f64;
+        let sine = (10.005 * PI / 180.).sin();
+        let y = 0.5 - 0.25 * ((1. + sine) / (1. - sine)).ln() / PI;
+        index
+            .get_tile(z, ((10.005 / 360. + 0.5) * z2) as u32, (y * z2) as u32)
+            .unwrap()
+    };
+    let ids = |index: &GeoJSONVT, z: u8| {
+        let mut ids: Vec<u64> = tile(index, z)
+            .features
+            .features
+            .iter()
+            .map(|f| match &f.id {
+                Some(Id::Number(id)) => id.as_u64().unwrap(),
+                _ => unreachable!(),
+            })
+            .collect();
+        ids.sort_unstable();
+        ids
+    };
+    assert_eq!(ids(&index, 11), [2, 3]);
+    assert_eq!(ids(&index, 12), [1, 2, 3]);
+
+    // the predicate sees the properties the filter leaves out of the tiles
+    let filtered = GeoJSONVT::from_geojson(
+        &geojson,
+        &Options {
+            tile: TileOptions {
+                properties: PropertyFilter::Include(vec!["name".to_string()]),
+                ..options.tile.clone()
+            },
+            ..options.clone()
+        },
+    )
+    .unwrap();
+    assert_eq!(ids(&filtered, 11), [2, 3]);
+    assert_eq!(ids(&filtered, 12), [1, 2, 3]);
+    assert!(tile(&filtered, 12).features.features.iter().all(|f| f
+        .properties
+        .as_ref()
+        .unwrap()
+        .keys()
+        .eq(["name"])));
+
+    // a loaded index couldn't apply the predicate to new tiles, so it isn't saved
+    assert!(matches!(
+        index.save(&mut Vec::new()),
+        Err(PersistError::UnsupportedCallback("feature_predicate"))
+    ));
+
+    // tiles are walked below tiles whose features are all hidden
+    #[cfg(any(feature = "mbtiles", feature = "pmtiles"))]
+    {
+        let index = GeoJSONVT::from_geojson(
+            &geojson,
+            &Options {
+                max_zoom: 4,
+                tile: TileOptions {
+                    feature_predicate: Some(Arc::new(|z, _, _| z >= 3)),
+                    ..TileOptions::default()
+                },
+                ..Options::default()
+            },
+        )
+        .unwrap();
+        let mut zooms = Vec::new();
+        index
+            .for_each_tile::<Error, _>(4, |z, _, _, _| {
+                zooms.push(z);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(zooms, [3, 4]);
+    }
+}
+
+#[test]
+fn projections() {
+    use crate::{CustomBounds, Equirectangular, PersistError, Projection, WebMercator};
+    use std::sync::Arc;
+
+    let collection = |coordinates: Vec<PointType>| {
+        GeoJson::from(FeatureCollection {
+            bbox: None,
+            features: vec![Feature {
+                geometry: Some(Geometry::new(geojson::Value::LineString(coordinates))),
+                ..Feature::default()
+            }],
+            foreign_members: None,
+        })
+    };
+    let index = |geojson: &GeoJson, projection: Arc<dyn Projection>| {
+        let options = Options {
+            projection,
+            validation: ValidationPolicy::Fail,
+            ..Options::default()
+        };
+        GeoJSONVT::from_geojson(geojson, &options).unwrap()
+    };
+    let geometry = |index: &GeoJSONVT, z, x, y| {
+        let tile = index.get_tile(z, x, y).unwrap();
+        tile.features
+            .features
+            .iter()
+            .map(|f| f.geometry.clone().unwrap().value)
+            .collect::<Vec<_>>()
+    };
+
+    // the equirectangular grid has two root tiles, its tiles are 180 / 2^z degrees on each side
+    let line = collection(vec![vec![100., 30.], vec![101., 30.]]);
+    let equirectangular = index(&line, Arc::new(Equirectangular));
+    assert_eq!(
+        equirectangular.covering_tiles(0, None).unwrap(),
+        [(0, 1, 0)]
+    );
+    assert_eq!(
+        geometry(&equirectangular, 0, 3, 0),
+        geometry(&equirectangular, 0, 1, 0)
+    );
+    assert_eq!(
+        equirectangular.get_tile(0, 1, 1).unwrap_err(),
+        Error::YOutOfRange { z: 0, y: 1 }
+    );
+    assert!(equirectangular
+        .generated_tiles()
+        .all(|(z, x, y)| x < 2 << z && y < 1 << z));
+    assert_eq!(
+        geometry(&equirectangular, 2, 6, 1),
+        [geojson::Value::LineString(vec![
+            vec![910., 1365.],
+            vec![1001., 1365.]
+        ])]
+    );
+
+    // Tokyo is in the 15th column and the third row of the 16 x 8 tiles of zoom 3
+    let tokyo = index(
+        &collection(vec![vec![139.69, 35.69], vec![140.69, 35.69]]),
+        Arc::new(Equirectangular),
+    );
+    assert_eq!(tokyo.covering_tiles(3, None).unwrap(), [(3, 14, 2)]);
+    assert!(!geometry(&tokyo, 3, 14, 2).is_empty());
+
+    // Web Mercator in metres gives the same tiles as in degrees
+    let half = 20037508.342789244;
+    let metres = |lon: f64, lat: f64| {
+        let y = ((90. + lat) * PI / 360.).tan().ln() / PI;
+        vec![lon / 180. * half, y * half]
+    };
+    let bounds = CustomBounds::new(-half, -half, half, half).unwrap();
+    let degrees = index(
+        &collection(vec![vec![10., 10.], vec![11., 12.]]),
+        Arc::new(WebMercator),
+    );
+    let projected = index(
+        &collection(vec![metres(10., 10.), metres(11., 12.)]),
+        Arc::new(bounds),
+    );
+    assert_eq!(
+        geometry(&projected, 5, 16, 15),
+        geometry(&degrees, 5, 16, 15)
+    );
+    assert!(!geometry(&projected, 5, 16, 15).is_empty());
+
+    // only grids of the whole world wrap around
+    let edge = |lon: f64| vec![lon, 10.];
+    let degrees = index(
+        &collection(vec![edge(-179.9), edge(-170.)]),
+        Arc::new(WebMercator),
+    );
+    let projected = index(
+        &collection(vec![metres(-179.9, 10.), metres(-170., 10.)]),
+        Arc::new(bounds),
+    );
+    assert_eq!(geometry(&degrees, 1, 1, 0).len(), 1);
+    assert!(!geometry(&projected, 1, 0, 0).is_empty());
+    assert!(geometry(&projected, 1, 1, 0).is_empty());
+
+    // features are clipped to custom bounds
+    let square = CustomBounds::new(0., 0., 100., 100.).unwrap();
+    let across = index(
+        &collection(vec![vec![-50., 50.], vec![50., 50.]]),
+        Arc::new(square),
+    );
+    assert_eq!(
+        geometry(&across, 0, 0, 0),
+        [geojson::Value::LineString(vec![
+            vec![0., 2048.],
+            vec![2048., 2048.]
+        ])]
+    );
+    let outside = index(
+        &collection(vec![vec![200., 50.], vec![300., 50.]]),
+        Arc::new(square),
+    );
+    assert!(outside.covering_tiles(0, None).unwrap().is_empty());
+    assert!(geometry(&outside, 0, 0, 0).is_empty());
+    for [min_x, min_y, max_x, max_y] in [
+        [0., 0., 0., 100.],
+        [0., 100., 100., 0.],
+        [0., 0., f64::NAN, 100.],
+    ] {
+        assert!(matches!(
+            CustomBounds::new(min_x, min_y, max_x, max_y),
+            Err(Error::InvalidBounds(_))
+        ));
+    }
+
+    let mut bytes = Vec::new();
+    projected.save(&mut bytes).unwrap();
+    let reloaded = GeoJSONVT::load(bytes.as_slice()).unwrap();
+    let reloaded_projection: &dyn std::any::Any = reloaded.options.projection.as_ref();
+    assert_eq!(reloaded_projection.downcast_ref(), Some(&bounds));
+
+    struct Sinusoidal;
+    impl Projection for Sinusoidal {
+        fn project(&self, position: &[f64]) -> [f64; 2] {
+            let x = position[0] * (position[1] * PI / 180.).cos();
+            [x / 360. + 0.5, (90. - position[1]) / 180.]
+        }
+        fn unproject(&self, x: f64, y: f64) -> PointType {
+            let lat = 90. - y * 180.;
+            vec![(x - 0.5) * 360. / (lat * PI / 180.).cos(), lat]
+        }
+        fn wraps(&self) -> bool {
+            false
+        }
+    }
+    let sinusoidal = index(&line, Arc::new(Sinusoidal));
+    assert!(matches!(
+        sinusoidal.save(&mut Vec::new()),
+        Err(PersistError::UnsupportedProjection)
+    ));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parallel_index() {
+    use crate::{Split, TileStore};
+
+    let geojson = GeoJson::from_reader(BufReader::new(
+        File::open("fixtures/us-states.json").unwrap(),
+    ))
+    .unwrap();
+    let options = Options {
+        index_max_zoom: 7,
+        index_max_points: 100,
+        editable: true,
+        ..Options::default()
+    };
+    let index = GeoJSONVT::from_geojson(&geojson, &options).unwrap();
+    let parallel = index.get_internal_tiles();
+    assert!(parallel.keys().any(|&id| id == crate::to_id(7, 37, 48)));
+
+    // the first pass of an edit slices sequentially
+    let mut sequential = TileStore::default();
+    index.split_tile(&mut sequential, &index.source, 0, 0, 0, Split::Rebuild);
+    assert_eq!(sequential.tiles.len(), parallel.len());
+    for (id, tile) in &parallel {
+        assert!(sequential.tiles[id] == *tile, "tile {id} differs");
+    }
+}
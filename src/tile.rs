@@ -1,12 +1,21 @@
+use std::sync::{Arc, OnceLock};
+
 use geojson::feature::Id;
-use geojson::{
-    Feature, FeatureCollection, Geometry, JsonObject, JsonValue, LineStringType, PointType,
-    PolygonType, Value,
-};
+use geojson::{FeatureCollection, JsonObject, JsonValue};
 use serde_json::Number;
 
+use crate::compact::{CompactFeature, CompactGeometry, CompactTile, TilePoint};
 use crate::types::*;
-use crate::{BBox, LinearRingType, MultiLineStringType, MultiPointType, MultiPolygonType};
+use crate::{BBox, GeometryType, PropertyFilter, TileOptions};
+
+// Integral values are written as integers
+pub(crate) fn json_number(value: f64) -> JsonValue {
+    if value.fract() == 0.0 {
+        JsonValue::Number(Number::from(value as i64))
+    } else {
+        Number::from_f64(value).map_or(JsonValue::Null, JsonValue::Number)
+    }
+}
 
 pub static EMPTY_TILE: Tile = Tile {
     features: FeatureCollection {
@@ -35,9 +44,12 @@ pub struct InternalTile {
     tolerance: f64,
     sq_tolerance: f64,
     line_metrics: bool,
-    pub source_features: VtFeatures,
+    clean_polygons: bool,
+    pub source_features: Arc<VtFeatures>,
     pub bbox: BBox,
-    pub tile: Tile,
+    pub hidden_features: u32, // features shown at higher zooms only
+    pub compact: Arc<CompactTile>,
+    pub geojson: OnceLock<Arc<Tile>>, // GeoJSON view, created on the first request
 }
 
 impl InternalTile {
@@ -46,258 +58,172 @@ impl InternalTile {
         z: u8,
         x: u32,
         y: u32,
-        extent: u16,
+        options: &TileOptions,
         tolerance: f64,
-        line_metrics: bool,
+        zoom: u8, // zoom of the tile grid, which the feature zoom ranges and callbacks refer to
     ) -> InternalTile {
         let mut tile = Self {
-            extent,
+            extent: options.extent,
             z,
             x,
             y,
             z2: 2i32.pow(z as u32) as f64,
             tolerance,
             sq_tolerance: tolerance * tolerance,
-            line_metrics,
-            source_features: vec![],
+            line_metrics: options.line_metrics,
+            clean_polygons: options.clean_polygons,
+            source_features: Arc::default(),
             bbox: Default::default(),
-            tile: Tile {
-                features: FeatureCollection {
-                    bbox: None,
-                    features: Vec::with_capacity(source.len()),
-                    foreign_members: None,
-                },
+            hidden_features: 0,
+            compact: Arc::new(CompactTile {
+                features: Vec::with_capacity(source.len()),
                 num_points: 0,
                 num_simplified: 0,
-            },
+            }),
+            geojson: OnceLock::new(),
         };
 
         for feature in source {
+            tile.bbox.min.x = feature.bbox.min.x.min(tile.bbox.min.x);
+            tile.bbox.min.y = feature.bbox.min.y.min(tile.bbox.min.y);
+            tile.bbox.max.x = feature.bbox.max.x.max(tile.bbox.max.x);
+            tile.bbox.max.y = feature.bbox.max.y.max(tile.bbox.max.y);
+
+            // the bbox covers hidden features too, as they are still sliced further down
+            let visible = feature.in_zoom_range(zoom)
+                && options.feature_predicate.as_ref().is_none_or(|predicate| {
+                    predicate(
+                        zoom,
+                        &feature.properties,
+                        GeometryType::of(&feature.geometry),
+                    )
+                });
+            if !visible {
+                tile.hidden_features += 1;
+                continue;
+            }
+
             let geom = &feature.geometry;
             // TODO assert!(feature.properties);
-            let props = &feature.properties;
+            let props = &tile_properties(&feature.properties, zoom, options);
             let id = &feature.id;
 
-            tile.tile.num_points += feature.num_points;
+            tile.compact_mut().num_points += feature.num_points;
 
             // TODO Verify if this is correct
-            tile.add_geometry_feature(
-                geom,
-                if props.is_empty() {
-                    None
-                } else {
-                    Some(props.clone())
-                },
-                id,
-            );
-
-            tile.bbox.min.x = feature.bbox.min.x.min(tile.bbox.min.x);
-            tile.bbox.min.y = feature.bbox.min.y.min(tile.bbox.min.y);
-            tile.bbox.max.x = feature.bbox.max.x.max(tile.bbox.max.x);
-            tile.bbox.max.y = feature.bbox.max.y.max(tile.bbox.max.y);
+            tile.add_geometry_feature(geom, props, id);
         }
 
         tile
     }
+
+    pub fn tile(&self) -> Arc<Tile> {
+        self.geojson
+            .get_or_init(|| Arc::new(self.compact.to_geojson()))
+            .clone()
+    }
 }
 
 impl InternalTile {
+    // the tile is only shared once it is cached, so this never clones
+    fn compact_mut(&mut self) -> &mut CompactTile {
+        Arc::make_mut(&mut self.compact)
+    }
+
+    fn push_feature(
+        &mut self,
+        geometry: CompactGeometry,
+        props: &Arc<JsonObject>,
+        id: &Option<Arc<Id>>,
+    ) {
+        self.compact_mut().features.push(CompactFeature {
+            geometry,
+            properties: props.clone(),
+            id: id.clone(),
+        });
+    }
+
     fn add_geometry_feature(
         &mut self,
         geom: &VtGeometry,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
+        props: &Arc<JsonObject>,
+        id: &Option<Arc<Id>>,
     ) {
         match geom {
-            VtGeometry::Empty(_empty) => unimplemented!(),
-            VtGeometry::Point(point) => self.add_point_feature(point, props, id),
+            VtGeometry::Empty(_empty) => {}
+            VtGeometry::Point(point) => {
+                let point = self.transform_point(point);
+                self.push_feature(CompactGeometry::Points(vec![point]), props, id);
+            }
             VtGeometry::MultiPoint(multi_point) => {
-                self.add_multi_point_feature(multi_point, props, id)
+                let points = self.transform_multi_point(multi_point);
+                if !points.is_empty() {
+                    self.push_feature(CompactGeometry::Points(points), props, id);
+                }
             }
             VtGeometry::LineString(line_string) => {
                 self.add_line_string_feature(line_string, props, id)
             }
             VtGeometry::MultiLineString(multi_line_string) => {
-                self.add_multi_line_string_feature(multi_line_string, props, id)
+                let lines = self.transform_multi_line_string(multi_line_string);
+                if !lines.is_empty() {
+                    self.push_feature(CompactGeometry::Lines(lines), props, id);
+                }
+            }
+            VtGeometry::Polygon(polygon) => {
+                let rings = self.transform_polygon(polygon);
+                if !rings.is_empty() {
+                    self.push_feature(CompactGeometry::Polygons(vec![rings]), props, id);
+                }
             }
-            VtGeometry::Polygon(polygon) => self.add_polygon_feature(polygon, props, id),
             VtGeometry::MultiPolygon(multi_polygon) => {
-                self.add_multi_polygon_feature(multi_polygon, props, id)
+                let polygons = self.transform_multi_polygon(multi_polygon);
+                if !polygons.is_empty() {
+                    self.push_feature(CompactGeometry::Polygons(polygons), props, id);
+                }
             }
             VtGeometry::GeometryCollection(geometry_collection) => {
-                self.add_geometry_collection_feature(geometry_collection, props, id)
+                for geom in geometry_collection {
+                    // TODO verify this is correct
+                    // FIXME should this become two features? what about props?
+                    self.add_geometry_feature(geom, props, id);
+                }
             }
         }
     }
 
-    fn add_point_feature(&mut self, value: &VtPoint, props: Option<JsonObject>, id: &Option<Id>) {
-        let geometry = Some(Geometry::new(Value::Point(self.transform_point(value))));
-        self.tile.features.features.push(Feature {
-            bbox: None,
-            geometry,
-            id: id.clone(),
-            properties: props,
-            foreign_members: None,
-        });
-    }
-
-    fn add_multi_point_feature(
-        &mut self,
-        value: &VtMultiPoint,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
-    ) {
-        let new_multi = self.transform_multi_point_feature(value);
-
-        match new_multi.len() {
-            0 => {}
-            1 => self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::Point(new_multi[0].clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            }),
-            _ => self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::MultiPoint(new_multi.clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            }),
-        }
-    }
     fn add_line_string_feature(
         &mut self,
         line: &VtLineString,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
+        props: &Arc<JsonObject>,
+        id: &Option<Arc<Id>>,
     ) {
         let new_line = self.transform_line_string(line);
-        if !new_line.is_empty() {
-            if self.line_metrics {
-                let mut new_props = props.unwrap_or_default();
-                let start = line.seg_start / line.dist;
-                new_props.insert(
-                    "mapbox_clip_start".to_string(),
-                    if start.fract() == 0.0 {
-                        JsonValue::Number(Number::from(start as i64))
-                    } else {
-                        JsonValue::Number(Number::from_f64(start).unwrap())
-                    },
-                );
-                let end = line.seg_end / line.dist;
-                
-                new_props.insert(
-                    "mapbox_clip_end".to_string(),
-                    if end.fract() == 0.0 {
-                        JsonValue::Number(Number::from(end as i64))
-                    } else {
-                        JsonValue::Number(Number::from_f64(end).unwrap())
-                    },
-                );
-                self.tile.features.features.push(Feature {
-                    bbox: None,
-                    geometry: Some(Geometry::new(Value::LineString(new_line.clone()))),
-                    id: id.clone(),
-                    properties: Some(new_props),
-                    foreign_members: None,
-                });
-            } else {
-                self.tile.features.features.push(Feature {
-                    bbox: None,
-                    geometry: Some(Geometry::new(Value::LineString(new_line.clone()))),
-                    id: id.clone(),
-                    properties: props,
-                    foreign_members: None,
-                });
-            }
-        }
-    }
-    fn add_multi_line_string_feature(
-        &mut self,
-        value: &VtMultiLineString,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
-    ) {
-        let new_multi = self.transform_multi_line_string(value);
-
-        match new_multi.len() {
-            0 => {}
-            1 => self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::LineString(new_multi[0].clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            }),
-            _ => self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::MultiLineString(new_multi.clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            }),
-        }
-    }
-    fn add_polygon_feature(
-        &mut self,
-        value: &VtPolygon,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
-    ) {
-        let new_polygon = self.transform_polygon(value);
-        if !new_polygon.is_empty() {
-            self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::Polygon(new_polygon.clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            });
+        if new_line.is_empty() {
+            return;
         }
-    }
-    fn add_multi_polygon_feature(
-        &mut self,
-        value: &VtMultiPolygon,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
-    ) {
-        let new_multi = self.transform_multi_polygon_feature(value);
-
-        match new_multi.len() {
-            0 => {}
-            1 => self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::Polygon(new_multi[0].clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            }),
-            _ => self.tile.features.features.push(Feature {
-                bbox: None,
-                geometry: Some(Geometry::new(Value::MultiPolygon(new_multi.clone()))),
-                id: id.clone(),
-                properties: props,
-                foreign_members: None,
-            }),
-        }
-    }
-    fn add_geometry_collection_feature(
-        &mut self,
-        value: &VtGeometryCollection,
-        props: Option<JsonObject>,
-        id: &Option<Id>,
-    ) {
-        for geom in value {
-            // TODO verify this is correct
-            // FIXME should this become two features? what about props?
-            self.add_geometry_feature(geom, props.clone(), id); // TODO clone is probably not correct here.
+        if self.line_metrics {
+            let mut new_props = JsonObject::clone(props);
+            // lines without length span the whole line
+            let (start, end) = if line.dist > 0. {
+                (line.seg_start / line.dist, line.seg_end / line.dist)
+            } else {
+                (0., 1.)
+            };
+            new_props.insert("mapbox_clip_start".to_string(), json_number(start));
+            new_props.insert("mapbox_clip_end".to_string(), json_number(end));
+            self.push_feature(
+                CompactGeometry::Lines(vec![new_line]),
+                &Arc::new(new_props),
+                id,
+            );
+        } else {
+            self.push_feature(CompactGeometry::Lines(vec![new_line]), props, id);
         }
     }
 
-    fn transform_multi_polygon_feature(&mut self, polygons: &VtMultiPolygon) -> MultiPolygonType {
-        let mut result: MultiPolygonType = Vec::with_capacity(polygons.len());
+    fn transform_multi_polygon(&mut self, polygons: &VtMultiPolygon) -> Vec<Vec<Vec<TilePoint>>> {
+        let mut result = Vec::with_capacity(polygons.len());
         for polygon in polygons {
             let p = self.transform_polygon(polygon);
             if !p.is_empty() {
@@ -307,16 +233,16 @@ impl InternalTile {
         result
     }
 
-    fn transform_multi_point_feature(&mut self, points: &VtMultiPoint) -> MultiPointType {
-        let mut result: MultiPointType = Vec::with_capacity(points.len());
+    fn transform_multi_point(&mut self, points: &VtMultiPoint) -> Vec<TilePoint> {
+        let mut result = Vec::with_capacity(points.len());
         for p in points {
             result.push(self.transform_point(p));
         }
         result
     }
 
-    fn transform_line_string(&mut self, line: &VtLineString) -> LineStringType {
-        let mut result: LineStringType = Vec::new();
+    fn transform_line_string(&mut self, line: &VtLineString) -> Vec<TilePoint> {
+        let mut result = Vec::new();
         if line.dist > self.tolerance {
             result.reserve(line.elements.len());
             for p in &line.elements {
@@ -328,8 +254,8 @@ impl InternalTile {
         result
     }
 
-    fn transform_multi_line_string(&mut self, lines: &VtMultiLineString) -> MultiLineStringType {
-        let mut result: MultiLineStringType = Vec::with_capacity(lines.len());
+    fn transform_multi_line_string(&mut self, lines: &VtMultiLineString) -> Vec<Vec<TilePoint>> {
+        let mut result = Vec::with_capacity(lines.len());
         for line in lines {
             if line.dist > self.tolerance {
                 result.push(self.transform_line_string(line));
@@ -338,40 +264,83 @@ impl InternalTile {
         result
     }
 
-    fn transform_polygon(&mut self, rings: &VtPolygon) -> PolygonType {
-        let mut result: PolygonType = Vec::with_capacity(rings.len());
-        for ring in rings {
+    fn transform_polygon(&mut self, rings: &VtPolygon) -> Vec<Vec<TilePoint>> {
+        let mut result = Vec::with_capacity(rings.len());
+        for (i, ring) in rings.iter().enumerate() {
             if ring.area > self.sq_tolerance {
-                result.push(self.transform_linear_ring(ring));
+                let mut ring = self.transform_linear_ring(ring);
+                if !self.clean_polygons || clean_ring(&mut ring, i == 0) {
+                    result.push(ring);
+                    continue;
+                }
+            }
+            // holes are dropped with their outer ring
+            if self.clean_polygons && i == 0 {
+                return Vec::new();
             }
         }
         result
     }
 
-    fn transform_point(&mut self, p: &VtPoint) -> PointType {
-        self.tile.num_simplified += 1;
-        Vec::from(&[
-            ((p.x * self.z2 - self.x as f64) * self.extent as f64).round(), // TODO do these have the right type. Shouldnt it be i16?
-            ((p.y * self.z2 - self.y as f64) * self.extent as f64).round(),
-        ])
+    fn transform_point(&mut self, p: &VtPoint) -> TilePoint {
+        self.compact_mut().num_simplified += 1;
+        [
+            ((p.x * self.z2 - self.x as f64) * self.extent as f64).round() as i32,
+            ((p.y * self.z2 - self.y as f64) * self.extent as f64).round() as i32,
+        ]
     }
 
-    fn transform_linear_ring(&mut self, ring: &VtLinearRing) -> LinearRingType {
-        let mut result: LinearRingType = Vec::new();
-        //println!("tile{:?}", self.z);
-        //println!("tolerance{:?}", self.tolerance);
-        //println!("sq_tolerance{:?}", self.sq_tolerance);
+    fn transform_linear_ring(&mut self, ring: &VtLinearRing) -> Vec<TilePoint> {
+        let mut result = Vec::new();
         if ring.area > self.sq_tolerance {
             result.reserve(ring.elements.len());
             for p in &ring.elements {
-                //eprintln!("p{:.6}, {:.6}, z{:.15}", p.x, p.y, p.z);
-                //eprintln!("{:?}", vec);
                 if p.z > self.sq_tolerance {
-                    let vec = self.transform_point(p);
-                    result.push(vec);
+                    result.push(self.transform_point(p));
                 }
             }
         }
         result
     }
 }
+
+// Properties of a feature in a tile at zoom `z`, shared with the feature unless they are filtered
+// or transformed. The property filter is applied when the features are converted, or here if a
+// predicate needs all properties.
+fn tile_properties(properties: &Arc<JsonObject>, z: u8, options: &TileOptions) -> Arc<JsonObject> {
+    let filter = options.feature_predicate.is_some() && options.properties != PropertyFilter::All;
+    if !filter && options.transform_properties.is_none() {
+        return properties.clone();
+    }
+    let mut properties = JsonObject::clone(properties);
+    if filter {
+        options.properties.apply(&mut properties);
+    }
+    if let Some(transform) = &options.transform_properties {
+        transform(z, &mut properties);
+    }
+    Arc::new(properties)
+}
+
+// Removes repeated points and orients the ring as vector tiles expect, outer rings clockwise and
+// holes counter-clockwise with y pointing down. Returns false if the ring collapsed.
+fn clean_ring(ring: &mut Vec<TilePoint>, outer: bool) -> bool {
+    ring.dedup();
+    if ring.len() > 1 && ring.first() != ring.last() {
+        ring.push(ring[0]);
+    }
+    let area = signed_area(ring);
+    if ring.len() < 4 || area == 0 {
+        return false;
+    }
+    if (area > 0) != outer {
+        ring.reverse();
+    }
+    true
+}
+
+fn signed_area(ring: &[TilePoint]) -> i64 {
+    ring.windows(2)
+        .map(|w| w[0][0] as i64 * w[1][1] as i64 - w[1][0] as i64 * w[0][1] as i64)
+        .sum()
+}
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use geojson::JsonObject;
 
 use crate::{BBox, Point2D};
@@ -18,6 +20,7 @@ pub enum VtGeometry {
 }
 
 #[cfg(test)]
+#[allow(dead_code)]
 impl VtGeometry {
     pub fn point(self) -> Option<VtPoint> {
         match self {
@@ -129,17 +132,20 @@ pub type VtMultiPolygon = Vec<VtPolygon>;
 #[derive(Debug, Clone, PartialEq)]
 pub struct VtFeature {
     pub geometry: VtGeometry,
-    pub properties: JsonObject,
-    pub id: Option<geojson::feature::Id>,
+    // shared by all tiles the feature is clipped into
+    pub properties: Arc<JsonObject>,
+    pub id: Option<Arc<geojson::feature::Id>>,
     pub bbox: BBox,
     pub num_points: u32,
+    pub min_zoom: u8, // zoom range the feature is shown in
+    pub max_zoom: u8,
 }
 
 impl VtFeature {
     pub fn new(
         geom: VtGeometry,
-        props: JsonObject,
-        id: Option<geojson::feature::Id>,
+        props: Arc<JsonObject>,
+        id: Option<Arc<geojson::feature::Id>>,
     ) -> Option<Self> {
         let mut feature = Self {
             geometry: geom,
@@ -147,6 +153,8 @@ impl VtFeature {
             id,
             bbox: BBox::new(Point2D::new(2., 1.), Point2D::new(-1., 0.)),
             num_points: 0,
+            min_zoom: 0,
+            max_zoom: u8::MAX,
         };
         feature.process_geometry();
         if feature.num_points == 0 {
@@ -156,6 +164,18 @@ impl VtFeature {
             Some(feature)
         }
     }
+
+    // A part of the feature, e.g. clipped to a tile
+    pub fn with_geometry(&self, geom: VtGeometry) -> Option<Self> {
+        let mut feature = Self::new(geom, self.properties.clone(), self.id.clone())?;
+        feature.min_zoom = self.min_zoom;
+        feature.max_zoom = self.max_zoom;
+        Some(feature)
+    }
+
+    pub fn in_zoom_range(&self, z: u8) -> bool {
+        self.min_zoom <= z && z <= self.max_zoom
+    }
 }
 
 pub(crate) fn for_each_point<F>(geometry: &mut VtGeometry, f: &mut F)
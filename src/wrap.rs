@@ -16,18 +16,18 @@ pub fn shift_coords(features: &mut VtFeatures, offset: f64) {
 }
 
 // Function to wrap features around the world edges
-pub fn wrap(features: &VtFeatures, buffer: f64, line_metrics: bool) -> VtFeatures {
+pub fn wrap(features: VtFeatures, buffer: f64, line_metrics: bool) -> VtFeatures {
     // left world copy
-    let mut left = clip::<0>(features, -1. - buffer, buffer, -1., 2., line_metrics);
+    let mut left = clip::<0>(&features, -1. - buffer, buffer, -1., 2., line_metrics);
     // right world copy
-    let mut right = clip::<0>(features, 1. - buffer, 2. + buffer, -1., 2., line_metrics);
+    let mut right = clip::<0>(&features, 1. - buffer, 2. + buffer, -1., 2., line_metrics);
 
     if left.is_empty() && right.is_empty() {
-        return features.clone();
+        return features;
     }
 
     // center world copy
-    let mut merged = clip::<0>(features, -buffer, 1. + buffer, -1., 2., line_metrics);
+    let mut merged = clip::<0>(&features, -buffer, 1. + buffer, -1., 2., line_metrics);
 
     if !left.is_empty() {
         // merge left into center